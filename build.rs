@@ -0,0 +1,4 @@
+// rebuild when migrations change, as they are embedded with `sqlx::migrate!`
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,6 @@
+CREATE TABLE willow_notifications (
+	id BIGINT NOT NULL,
+	hostname VARCHAR NOT NULL,
+	data VARCHAR NOT NULL,
+	PRIMARY KEY (id, hostname)
+);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS willow_ota_history;
@@ -0,0 +1,13 @@
+CREATE TABLE willow_ota_history (
+	id VARCHAR NOT NULL,
+	mac_addr VARCHAR NOT NULL,
+	hostname VARCHAR,
+	version_from VARCHAR NOT NULL,
+	version_to VARCHAR NOT NULL,
+	status VARCHAR NOT NULL,
+	started_at BIGINT NOT NULL,
+	updated_at BIGINT NOT NULL,
+	PRIMARY KEY (id)
+);
+
+CREATE INDEX willow_ota_history_mac_addr ON willow_ota_history (mac_addr);
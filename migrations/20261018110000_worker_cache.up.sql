@@ -0,0 +1,6 @@
+CREATE TABLE willow_worker_cache (
+	name VARCHAR NOT NULL,
+	data VARCHAR NOT NULL,
+	updated_at BIGINT NOT NULL,
+	PRIMARY KEY (name)
+);
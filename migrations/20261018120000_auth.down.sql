@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS willow_api_tokens;
+DROP TABLE IF EXISTS willow_sessions;
+DROP TABLE IF EXISTS willow_users;
@@ -0,0 +1,25 @@
+CREATE TABLE willow_users (
+	username VARCHAR NOT NULL,
+	password_hash VARCHAR NOT NULL,
+	created_at BIGINT NOT NULL,
+	PRIMARY KEY (username)
+);
+
+CREATE TABLE willow_sessions (
+	token_hash VARCHAR NOT NULL,
+	username VARCHAR NOT NULL,
+	created_at BIGINT NOT NULL,
+	expires_at BIGINT NOT NULL,
+	PRIMARY KEY (token_hash)
+);
+
+CREATE TABLE willow_api_tokens (
+	id VARCHAR NOT NULL,
+	name VARCHAR NOT NULL,
+	token_hash VARCHAR NOT NULL,
+	username VARCHAR NOT NULL,
+	created_at BIGINT NOT NULL,
+	last_used_at BIGINT,
+	PRIMARY KEY (id),
+	UNIQUE (token_hash)
+);
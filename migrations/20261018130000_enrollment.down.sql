@@ -0,0 +1,2 @@
+ALTER TABLE willow_clients DROP COLUMN token_hash;
+ALTER TABLE willow_clients DROP COLUMN status;
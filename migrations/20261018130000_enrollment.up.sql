@@ -0,0 +1,2 @@
+ALTER TABLE willow_clients ADD COLUMN status VARCHAR NOT NULL DEFAULT 'pending';
+ALTER TABLE willow_clients ADD COLUMN token_hash VARCHAR;
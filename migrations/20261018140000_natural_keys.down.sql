@@ -0,0 +1,34 @@
+CREATE TABLE willow_clients_old (
+	id INTEGER NOT NULL,
+	mac_addr VARCHAR NOT NULL,
+	label VARCHAR NOT NULL,
+	status VARCHAR NOT NULL DEFAULT 'pending',
+	token_hash VARCHAR,
+	PRIMARY KEY (id),
+	UNIQUE (mac_addr)
+);
+
+INSERT INTO willow_clients_old (id, mac_addr, label, status, token_hash)
+	SELECT ROW_NUMBER() OVER (ORDER BY mac_addr), mac_addr, label, status, token_hash FROM willow_clients;
+
+DROP TABLE willow_clients;
+
+ALTER TABLE willow_clients_old RENAME TO willow_clients;
+
+CREATE TABLE willow_config_old (
+	id INTEGER NOT NULL,
+	config_type VARCHAR(8) NOT NULL,
+	config_name VARCHAR NOT NULL,
+	config_namespace VARCHAR(4),
+	config_value VARCHAR,
+	PRIMARY KEY (id),
+	UNIQUE (config_type, config_name)
+);
+
+INSERT INTO willow_config_old (id, config_type, config_name, config_namespace, config_value)
+	SELECT ROW_NUMBER() OVER (ORDER BY config_type, config_name), config_type, config_name, config_namespace, config_value
+	FROM willow_config;
+
+DROP TABLE willow_config;
+
+ALTER TABLE willow_config_old RENAME TO willow_config;
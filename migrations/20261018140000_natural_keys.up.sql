@@ -0,0 +1,32 @@
+-- The surrogate ids of the initial schema have no default on Postgres, so inserts without an id fail there.
+-- Rebuild the tables keyed by the columns they are looked up and upserted by.
+
+CREATE TABLE willow_clients_new (
+	mac_addr VARCHAR NOT NULL,
+	label VARCHAR NOT NULL,
+	status VARCHAR NOT NULL DEFAULT 'pending',
+	token_hash VARCHAR,
+	PRIMARY KEY (mac_addr)
+);
+
+INSERT INTO willow_clients_new (mac_addr, label, status, token_hash)
+	SELECT mac_addr, label, status, token_hash FROM willow_clients;
+
+DROP TABLE willow_clients;
+
+ALTER TABLE willow_clients_new RENAME TO willow_clients;
+
+CREATE TABLE willow_config_new (
+	config_type VARCHAR(8) NOT NULL,
+	config_name VARCHAR NOT NULL,
+	config_namespace VARCHAR(4),
+	config_value VARCHAR,
+	PRIMARY KEY (config_type, config_name)
+);
+
+INSERT INTO willow_config_new (config_type, config_name, config_namespace, config_value)
+	SELECT config_type, config_name, config_namespace, config_value FROM willow_config;
+
+DROP TABLE willow_config;
+
+ALTER TABLE willow_config_new RENAME TO willow_config;
@@ -0,0 +1,281 @@
+use anyhow::Context;
+use axum::{
+    Extension, Json, Router,
+    extract::{Query, State},
+    middleware,
+    response::IntoResponse,
+    routing::{get, post},
+};
+use axum_extra::extract::{
+    CookieJar,
+    cookie::{Cookie, SameSite},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        AuthUser, SESSION_COOKIE, SESSION_LIFETIME, create_session, generate_token, hash_password,
+        hash_token, require_auth, verify_password,
+    },
+    db::auth::WillowApiToken,
+    error::WasApiError,
+    state::SharedState,
+    util::now_ms,
+};
+
+#[derive(Debug, Deserialize)]
+struct ApiPostAuth {
+    action: ApiAuthAction,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ApiAuthAction {
+    Create,
+    Delete,
+}
+
+#[derive(Deserialize)]
+struct PostCredentials {
+    password: String,
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostUser {
+    password: Option<String>,
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostToken {
+    id: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiNewToken {
+    #[serde(flatten)]
+    info: WillowApiToken,
+    /// The token itself, only returned once when it is created.
+    token: String,
+}
+
+pub fn auth_routes(state: SharedState) -> Router<()> {
+    Router::new()
+        .route("/token", get(get_api_auth_token))
+        .route("/token", post(post_api_auth_token))
+        .route("/user", get(get_api_auth_user))
+        .route("/user", post(post_api_auth_user))
+        .route_layer(middleware::from_fn_with_state(
+            SharedState::clone(&state),
+            require_auth,
+        ))
+        .route("/login", post(post_api_auth_login))
+        .route("/logout", post(post_api_auth_logout))
+        .route("/setup", post(post_api_auth_setup))
+        .with_state(state)
+}
+
+fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, token))
+        .http_only(true)
+        .max_age(SESSION_LIFETIME.try_into().unwrap_or_default())
+        .path("/")
+        .same_site(SameSite::Strict)
+        .build()
+}
+
+async fn post_api_auth_login(
+    State(state): State<SharedState>,
+    jar: CookieJar,
+    Json(credentials): Json<PostCredentials>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("POST /api/auth/login - username: {}", credentials.username);
+
+    let hash = state
+        .db_pool()
+        .get_user_password_hash(&credentials.username)
+        .await?;
+
+    let valid = match hash {
+        Some(hash) => verify_password(credentials.password, hash).await,
+        None => false,
+    };
+    if !valid {
+        tracing::warn!("failed login for user {}", credentials.username);
+        return Err(WasApiError::UnauthorizedError(String::from(
+            "invalid username or password",
+        )));
+    }
+
+    let token = create_session(&state, &credentials.username).await?;
+
+    Ok((jar.add(session_cookie(token)), Json("success")))
+}
+
+async fn post_api_auth_logout(
+    State(state): State<SharedState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("POST /api/auth/logout");
+
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        state
+            .db_pool()
+            .delete_session(&hash_token(cookie.value()))
+            .await?;
+    }
+
+    Ok((
+        jar.remove(Cookie::build(SESSION_COOKIE).path("/")),
+        Json("success"),
+    ))
+}
+
+/// Create the first admin user. Only allowed while no users exist.
+async fn post_api_auth_setup(
+    State(state): State<SharedState>,
+    jar: CookieJar,
+    Json(credentials): Json<PostCredentials>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("POST /api/auth/setup - username: {}", credentials.username);
+
+    check_credentials(&credentials.username, &credentials.password)?;
+    let hash = hash_password(credentials.password).await?;
+    let created = state
+        .db_pool()
+        .save_first_user(&credentials.username, &hash, now_ms())
+        .await
+        .context(format!("failed to save user {}", credentials.username))?;
+    if !created {
+        return Err(WasApiError::BadRequestError(String::from(
+            "an admin user already exists",
+        )));
+    }
+
+    let token = create_session(&state, &credentials.username).await?;
+
+    Ok((jar.add(session_cookie(token)), Json("success")))
+}
+
+async fn get_api_auth_user(
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("GET /api/auth/user");
+
+    Ok(Json(state.db_pool().get_users().await?))
+}
+
+async fn post_api_auth_user(
+    State(state): State<SharedState>,
+    query: Query<ApiPostAuth>,
+    Json(parameters): Json<PostUser>,
+) -> Result<Json<&'static str>, WasApiError> {
+    tracing::debug!("POST /api/auth/user - query: {query:?}");
+
+    match query.action {
+        ApiAuthAction::Create => {
+            let Some(password) = parameters.password else {
+                return Err(WasApiError::BadRequestError(String::from(
+                    "password is required",
+                )));
+            };
+            save_user(&state, &parameters.username, password).await?;
+        }
+        ApiAuthAction::Delete => {
+            let users = state.db_pool().get_users().await?;
+            if users.iter().all(|u| u.username == parameters.username) {
+                return Err(WasApiError::BadRequestError(String::from(
+                    "cannot delete the last admin user",
+                )));
+            }
+            state.db_pool().delete_user(&parameters.username).await?;
+        }
+    }
+
+    Ok(Json("success"))
+}
+
+async fn get_api_auth_token(
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("GET /api/auth/token");
+
+    Ok(Json(state.db_pool().get_api_tokens().await?))
+}
+
+async fn post_api_auth_token(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthUser>,
+    query: Query<ApiPostAuth>,
+    Json(parameters): Json<PostToken>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("POST /api/auth/token - query: {query:?}, parameters: {parameters:?}");
+
+    match query.action {
+        ApiAuthAction::Create => {
+            let Some(name) = parameters.name.filter(|n| !n.trim().is_empty()) else {
+                return Err(WasApiError::BadRequestError(String::from(
+                    "name is required",
+                )));
+            };
+
+            let token = generate_token()?;
+            let info = WillowApiToken {
+                id: Uuid::new_v4().to_string(),
+                name,
+                username: user.username,
+                created_at: now_ms(),
+                last_used_at: None,
+            };
+            state
+                .db_pool()
+                .save_api_token(&info, &hash_token(&token))
+                .await
+                .context("failed to save API token")?;
+
+            Ok(Json(ApiNewToken { info, token }).into_response())
+        }
+        ApiAuthAction::Delete => {
+            let Some(id) = parameters.id else {
+                return Err(WasApiError::BadRequestError(String::from("id is required")));
+            };
+            if !state.db_pool().delete_api_token(&id).await? {
+                return Err(WasApiError::NotFoundError(format!(
+                    "API token {id} not found"
+                )));
+            }
+
+            Ok(Json("success").into_response())
+        }
+    }
+}
+
+async fn save_user(
+    state: &SharedState,
+    username: &str,
+    password: String,
+) -> Result<(), WasApiError> {
+    check_credentials(username, &password)?;
+
+    let hash = hash_password(password).await?;
+    state
+        .db_pool()
+        .save_user(username, &hash, now_ms())
+        .await
+        .context(format!("failed to save user {username}"))?;
+
+    Ok(())
+}
+
+fn check_credentials(username: &str, password: &str) -> Result<(), WasApiError> {
+    if username.trim().is_empty() || password.is_empty() {
+        return Err(WasApiError::BadRequestError(String::from(
+            "username and password must not be empty",
+        )));
+    }
+
+    Ok(())
+}
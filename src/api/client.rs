@@ -2,12 +2,23 @@ use anyhow::Context;
 use axum::{
     Json, Router,
     extract::{Query, State},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::{get, post},
 };
+use eui48::MacAddress;
 use serde::{Deserialize, Serialize};
 
-use crate::{error::WasApiError, state::SharedState, willow::client::WillowClient};
+use crate::{
+    enrollment::{approve_client, require_approved, revoke_client},
+    error::WasApiError,
+    notify::add_notification,
+    ota::{ota_url, start_ota_tracking},
+    state::SharedState,
+    willow::{
+        client::WillowClient,
+        messages::{WillowAction, WillowCommand, WillowNotifyData, WillowOtaStart},
+    },
+};
 
 #[derive(Debug, Deserialize)]
 struct ApiPostClient {
@@ -24,33 +35,47 @@ enum ApiClientAction {
     Update,
 }
 
-#[derive(Clone, Debug, Serialize)]
-#[serde(rename_all = "snake_case", tag = "cmd")]
-enum WillowAction {
-    OtaStart(WillowOtaStart),
-    Restart,
+#[derive(Debug, Deserialize)]
+struct ApiPostClientEnrollment {
+    action: ApiClientEnrollmentAction,
 }
 
-#[derive(Clone, Debug, Default, Serialize)]
-struct WillowOtaStart {
-    ota_url: String,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ApiClientEnrollmentAction {
+    Approve,
+    Revoke,
 }
 
-#[derive(Serialize)]
-struct WillowCommand {
-    #[serde(flatten)]
-    cmd: WillowAction,
+#[derive(Debug, Serialize)]
+struct ApiClientToken {
+    mac_addr: String,
+    /// Device token, only returned once when the client is approved.
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetClientOta {
+    mac_addr: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct PostClient {
-    hostname: String,
+    data: Option<WillowNotifyData>,
+    hostname: Option<String>,
+    label: Option<String>,
+    mac_addr: Option<String>,
+    platform: Option<String>,
+    version: Option<String>,
 }
 
 pub fn client_routes(state: SharedState) -> Router<()> {
     Router::new()
         .route("/", get(get_api_client))
         .route("/", post(post_api_client))
+        .route("/enrollment", get(get_api_client_enrollment))
+        .route("/enrollment", post(post_api_client_enrollment))
+        .route("/ota", get(get_api_client_ota))
         .with_state(state)
 }
 
@@ -60,6 +85,61 @@ async fn get_api_client(State(state): State<SharedState>) -> impl IntoResponse {
     Json(clients)
 }
 
+async fn get_api_client_enrollment(
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("GET /api/client/enrollment");
+
+    Ok(Json(state.db_pool().get_willow_clients().await?))
+}
+
+async fn post_api_client_enrollment(
+    State(state): State<SharedState>,
+    query: Query<ApiPostClientEnrollment>,
+    Json(parameters): Json<PostClient>,
+) -> Result<Response, WasApiError> {
+    tracing::debug!("POST /api/client/enrollment - query: {query:?}, parameters: {parameters:?}");
+
+    let mac_addr = get_mac_addr(&state, &parameters).await?;
+
+    match query.action {
+        ApiClientEnrollmentAction::Approve => {
+            let token = approve_client(&state, &mac_addr).await?;
+            Ok(Json(ApiClientToken { mac_addr, token }).into_response())
+        }
+        ApiClientEnrollmentAction::Revoke => {
+            revoke_client(&state, &mac_addr).await?;
+            Ok(Json("success").into_response())
+        }
+    }
+}
+
+async fn get_api_client_ota(
+    State(state): State<SharedState>,
+    Query(query): Query<GetClientOta>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("GET /api/client/ota - query: {query:?}");
+
+    let mac_addr = query
+        .mac_addr
+        .map(|mac_addr| {
+            MacAddress::parse_str(&mac_addr)
+                .map(|m| m.to_hex_string())
+                .map_err(|e| {
+                    WasApiError::BadRequestError(format!("invalid MAC address {mac_addr}: {e}"))
+                })
+        })
+        .transpose()?;
+
+    let history = state
+        .db_pool()
+        .get_ota_history(mac_addr.as_deref())
+        .await
+        .context("failed to get OTA history")?;
+
+    Ok(Json(history))
+}
+
 async fn post_api_client(
     State(state): State<SharedState>,
     query: Query<ApiPostClient>,
@@ -67,37 +147,352 @@ async fn post_api_client(
 ) -> Result<Json<&'static str>, WasApiError> {
     tracing::debug!("POST /api/client - query: {query:?}, parameters: {parameters:?}");
 
-    if let Ok(client_id) = state.get_client_id_by_hostname(&parameters.hostname).await {
+    let cmd = match query.action {
+        ApiClientAction::Config => return post_api_client_config(&state, parameters).await,
+        ApiClientAction::Identify => WillowCommand {
+            cmd: WillowAction::Identify,
+        },
+        ApiClientAction::Notify => return post_api_client_notify(&state, parameters).await,
+        ApiClientAction::Restart => WillowCommand {
+            cmd: WillowAction::Restart,
+        },
+        ApiClientAction::Update => WillowCommand {
+            cmd: WillowAction::OtaStart(get_ota_start(&state, &parameters).await?),
+        },
+    };
+
+    let Some(hostname) = parameters.hostname else {
+        return Err(WasApiError::BadRequestError(String::from(
+            "hostname is required",
+        )));
+    };
+
+    if let Ok(client_id) = state.get_client_id_by_hostname(&hostname).await {
+        require_approved(&state, client_id)
+            .await
+            .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+
         let connmgr = state.connmgr().read().await;
         if let Some(msg_tx) = connmgr.get(&client_id) {
             let msg_tx = msg_tx.clone();
             drop(connmgr);
 
-            let cmd = match query.action {
-                ApiClientAction::Config | ApiClientAction::Identify | ApiClientAction::Notify => {
-                    todo!("not implemented")
-                }
-                ApiClientAction::Restart => WillowCommand {
-                    cmd: WillowAction::Restart,
-                },
-                ApiClientAction::Update => WillowCommand {
-                    cmd: WillowAction::OtaStart(WillowOtaStart::default()),
-                },
-            };
-
             let msg =
                 serde_json::to_string_pretty(&cmd).context("failed to serialize WillowCommand")?;
 
             msg_tx.send(msg.into()).await.context(format!(
-                "failed to send WillowCommand to client with hostname {}",
-                parameters.hostname,
+                "failed to send WillowCommand to client with hostname {hostname}",
             ))?;
+
+            if let (ApiClientAction::Update, Some(version)) = (&query.action, &parameters.version)
+                && let Err(e) = start_ota_tracking(&state, client_id, version).await
+            {
+                tracing::error!("failed to track OTA of client {hostname}: {e:#}");
+            }
         }
         return Ok(Json("success"));
     }
 
     Err(WasApiError::InternalServerError(format!(
-        "client with hostname {} not found",
-        parameters.hostname,
+        "client with hostname {hostname} not found",
     )))
 }
+
+async fn post_api_client_config(
+    state: &SharedState,
+    parameters: PostClient,
+) -> Result<Json<&'static str>, WasApiError> {
+    let Some(label) = &parameters.label else {
+        return Err(WasApiError::BadRequestError(String::from(
+            "label is required",
+        )));
+    };
+    let label = label.trim();
+    let mac_addr = get_mac_addr(state, &parameters).await?;
+
+    state
+        .db_pool()
+        .save_willow_client_label(&mac_addr, label)
+        .await
+        .context(format!("failed to save label for client {mac_addr}"))?;
+    state.set_client_label(&mac_addr, label).await;
+
+    Ok(Json("success"))
+}
+
+/// Get the MAC address from the parameters, or of the connected client with the hostname in the parameters.
+async fn get_mac_addr(state: &SharedState, parameters: &PostClient) -> Result<String, WasApiError> {
+    let mac_addr = match (&parameters.mac_addr, &parameters.hostname) {
+        (Some(mac_addr), _) => MacAddress::parse_str(mac_addr)
+            .map_err(|e| {
+                WasApiError::BadRequestError(format!("invalid MAC address {mac_addr}: {e}"))
+            })?
+            .to_hex_string(),
+        (None, Some(hostname)) => state
+            .get_mac_addr_by_hostname(hostname)
+            .await
+            .map_err(|e| WasApiError::BadRequestError(e.to_string()))?,
+        (None, None) => {
+            return Err(WasApiError::BadRequestError(String::from(
+                "either hostname or mac_addr is required",
+            )));
+        }
+    };
+
+    Ok(mac_addr)
+}
+
+async fn post_api_client_notify(
+    state: &SharedState,
+    parameters: PostClient,
+) -> Result<Json<&'static str>, WasApiError> {
+    let (Some(hostname), Some(data)) = (parameters.hostname, parameters.data) else {
+        return Err(WasApiError::BadRequestError(String::from(
+            "hostname and data are required",
+        )));
+    };
+
+    add_notification(state, &hostname, data)
+        .await
+        .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+
+    Ok(Json("success"))
+}
+
+async fn get_ota_start(
+    state: &SharedState,
+    parameters: &PostClient,
+) -> Result<WillowOtaStart, WasApiError> {
+    let (Some(hostname), Some(version)) = (&parameters.hostname, &parameters.version) else {
+        return Err(WasApiError::BadRequestError(String::from(
+            "hostname and version are required",
+        )));
+    };
+
+    let client_id = state
+        .get_client_id_by_hostname(hostname)
+        .await
+        .map_err(|e| WasApiError::NotFoundError(e.to_string()))?;
+    // checked before the OTA build is cached for the client
+    require_approved(state, client_id)
+        .await
+        .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+    let client_platform = state
+        .clients()
+        .read()
+        .await
+        .get(&client_id)
+        .and_then(|c| c.platform().clone());
+
+    let platform = match (&parameters.platform, client_platform) {
+        (Some(platform), Some(client_platform)) if *platform != client_platform => {
+            return Err(WasApiError::BadRequestError(format!(
+                "platform {platform} does not match platform {client_platform} of client {hostname}"
+            )));
+        }
+        (Some(platform), _) => platform.clone(),
+        (None, Some(client_platform)) => client_platform,
+        (None, None) => {
+            return Err(WasApiError::BadRequestError(format!(
+                "platform of client {hostname} is unknown"
+            )));
+        }
+    };
+
+    let worker_data = state.worker_data().await;
+    let asset = worker_data.ota_asset(version, &platform).ok_or_else(|| {
+        WasApiError::NotFoundError(format!(
+            "no OTA build of release {version} for platform {platform}"
+        ))
+    })?;
+
+    let ota_url = ota_url(state, version, &platform, asset).await?;
+
+    Ok(WillowOtaStart { ota_url })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        Json,
+        extract::{Query, State},
+        response::IntoResponse,
+    };
+    use eui48::MacAddress;
+    use serde_json::{Value, json};
+
+    use super::{ApiClientAction, ApiPostClient, PostClient, get_api_client, post_api_client};
+    use crate::{
+        enrollment::{approve_client, enroll_client},
+        ota::send_ota_start,
+        rollout::{RolloutRequest, RolloutTarget, start_rollout},
+        state::{SharedState, connect_test_client, create_test_state},
+        willow::client::WillowClient,
+    };
+
+    const MAC_ADDR: &str = "7c:df:a1:e7:a8:98";
+
+    fn parameters() -> PostClient {
+        PostClient {
+            data: None,
+            hostname: Some(String::from("willow-kitchen")),
+            label: None,
+            mac_addr: None,
+            platform: None,
+            version: Some(String::from("0.3.1")),
+        }
+    }
+
+    async fn post(state: &SharedState, action: ApiClientAction) -> bool {
+        post_api_client(
+            State(Arc::clone(state)),
+            Query(ApiPostClient { action }),
+            Json(parameters()),
+        )
+        .await
+        .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_client_label() {
+        let state = create_test_state().await;
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_hostname(String::from("willow-kitchen"));
+        client.set_mac_addr(MacAddress::parse_str(MAC_ADDR).expect("invalid MAC address"));
+        let (_client_id, _msg_rx) = connect_test_client(&state, client).await;
+        state
+            .db_pool()
+            .save_willow_client(MAC_ADDR)
+            .await
+            .expect("failed to save client");
+
+        let Json(response) = post_api_client(
+            State(Arc::clone(&state)),
+            Query(ApiPostClient {
+                action: ApiClientAction::Config,
+            }),
+            Json(PostClient {
+                label: Some(String::from(" Kitchen ")),
+                ..parameters()
+            }),
+        )
+        .await
+        .expect("failed to set label");
+        assert_eq!(response, "success");
+
+        assert_eq!(
+            state
+                .db_pool()
+                .get_willow_client_label(MAC_ADDR)
+                .await
+                .expect("failed to get label")
+                .as_deref(),
+            Some("Kitchen")
+        );
+        let response = get_api_client(State(Arc::clone(&state)))
+            .await
+            .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("failed to read response");
+        let clients: Value = serde_json::from_slice(&body).expect("invalid response");
+        assert_eq!(clients[0]["label"], "Kitchen");
+    }
+
+    #[tokio::test]
+    async fn test_identify() {
+        let state = create_test_state().await;
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_hostname(String::from("willow-kitchen"));
+        let (client_id, mut msg_rx) = connect_test_client(&state, client).await;
+        enroll_client(&state, client_id, MAC_ADDR)
+            .await
+            .expect("failed to enroll client");
+
+        assert!(post(&state, ApiClientAction::Identify).await);
+        let msg = msg_rx.try_recv().expect("failed to receive identify");
+        let msg: Value =
+            serde_json::from_str(msg.to_text().expect("invalid message")).expect("invalid JSON");
+        assert_eq!(msg, json!({"cmd": "identify"}));
+
+        let unknown = post_api_client(
+            State(Arc::clone(&state)),
+            Query(ApiPostClient {
+                action: ApiClientAction::Identify,
+            }),
+            Json(PostClient {
+                hostname: Some(String::from("willow-unknown")),
+                ..parameters()
+            }),
+        )
+        .await;
+        assert!(unknown.is_err());
+        assert!(msg_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pending_client_commands() {
+        let state = create_test_state().await;
+        state
+            .db_pool()
+            .save_was_config(&json!({"device_enrollment": "true", "ota_source": "upstream"}))
+            .await
+            .expect("failed to save WAS config");
+
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.0",
+        );
+        client.set_hostname(String::from("willow-kitchen"));
+        client.set_mac_addr(MacAddress::parse_str(MAC_ADDR).expect("invalid MAC address"));
+        client.set_platform(String::from("ESP32-S3-BOX"));
+        let (client_id, mut msg_rx) = connect_test_client(&state, client).await;
+
+        for action in [
+            ApiClientAction::Identify,
+            ApiClientAction::Restart,
+            ApiClientAction::Update,
+        ] {
+            assert!(!post(&state, action).await);
+        }
+        assert!(send_ota_start(&state, client_id, "0.3.1").await.is_err());
+        assert!(
+            start_rollout(
+                &state,
+                RolloutRequest {
+                    batch_size: 1,
+                    target: RolloutTarget::All,
+                    timeout: 600,
+                    version: String::from("0.3.1"),
+                },
+            )
+            .await
+            .is_err()
+        );
+        assert!(msg_rx.try_recv().is_err());
+
+        approve_client(&state, MAC_ADDR)
+            .await
+            .expect("failed to approve client");
+        assert!(
+            msg_rx
+                .try_recv()
+                .is_ok_and(|m| m.to_text().is_ok_and(|m| m.contains("token")))
+        );
+
+        assert!(post(&state, ApiClientAction::Identify).await);
+        assert!(
+            msg_rx
+                .try_recv()
+                .is_ok_and(|m| m.to_text().is_ok_and(|m| m.contains("identify")))
+        );
+    }
+}
@@ -1,15 +1,24 @@
+use anyhow::Context;
 use axum::{
-    Json, Router,
+    Extension, Json, Router,
     extract::{Query, State},
     response::IntoResponse,
     routing::{get, post},
 };
 use reqwest::StatusCode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use strum::AsRefStr;
 
-use crate::{state::SharedState, willow::messages::WillowMsgConfig};
+use crate::{
+    auth::{AuthMethod, AuthUser},
+    config::WasConfig,
+    enrollment::{require_approved, require_authenticated},
+    error::WasApiError,
+    secret::{redact_config, redact_nvs},
+    state::SharedState,
+    willow::messages::{WillowMsgConfig, WillowMsgNvs},
+};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -17,6 +26,7 @@ enum GetApiConfigType {
     Config,
     Nvs,
     Tz,
+    Was,
 }
 
 #[allow(dead_code)]
@@ -24,6 +34,9 @@ enum GetApiConfigType {
 struct GetApiConfig {
     #[serde(default)]
     default: bool,
+    /// Return secrets instead of a placeholder, only allowed for users logged in to a session.
+    #[serde(default)]
+    reveal: bool,
     #[serde(rename = "type")]
     config_type: GetApiConfigType,
 }
@@ -37,7 +50,8 @@ enum PostApiConfigType {
     Was,
 }
 
-#[derive(Debug, Deserialize)]
+/// Holds secrets, so it must not be logged.
+#[derive(Deserialize)]
 struct PostApiConfigBody {
     #[serde(flatten)]
     config: Option<Value>,
@@ -60,10 +74,19 @@ pub fn config_routes(state: SharedState) -> Router<()> {
 
 async fn get_api_config(
     State(state): State<SharedState>,
+    Extension(user): Extension<AuthUser>,
     query: Query<GetApiConfig>,
 ) -> impl IntoResponse {
     tracing::debug!("GET /api/config");
-    let worker_data = state.worker_data();
+
+    if query.reveal && user.method != AuthMethod::Session {
+        return WasApiError::UnauthorizedError(String::from(
+            "revealing secrets requires logging in, API tokens are not allowed",
+        ))
+        .into_response();
+    }
+
+    let worker_data = state.worker_data().await;
 
     match &query.config_type {
         GetApiConfigType::Config => {
@@ -71,7 +94,12 @@ async fn get_api_config(
                 if let Some(config) = worker_data.config() {
                     return Json(config).into_response();
                 }
-            } else if let Ok(config) = state.db_pool().get_willow_config().await {
+            } else if let Ok(config) = state.db_pool().get_willow_config().await
+                && let Ok(mut config) = serde_json::to_value(config)
+            {
+                if !query.reveal {
+                    redact_config(&mut config);
+                }
                 return Json(config).into_response();
             }
         }
@@ -80,7 +108,12 @@ async fn get_api_config(
                 if let Some(nvs) = worker_data.nvs() {
                     return Json(nvs).into_response();
                 }
-            } else if let Ok(nvs) = state.db_pool().get_willow_nvs().await {
+            } else if let Ok(nvs) = state.db_pool().get_willow_nvs().await
+                && let Ok(mut nvs) = serde_json::to_value(nvs)
+            {
+                if !query.reveal {
+                    redact_nvs(&mut nvs);
+                }
                 return Json(nvs).into_response();
             }
         }
@@ -89,6 +122,13 @@ async fn get_api_config(
                 return Json(tz).into_response();
             }
         }
+        GetApiConfigType::Was => {
+            if query.default {
+                return Json(WasConfig::default()).into_response();
+            } else if let Ok(was) = state.db_pool().get_was_config().await {
+                return Json(was).into_response();
+            }
+        }
     }
 
     StatusCode::NOT_FOUND.into_response()
@@ -98,42 +138,168 @@ async fn post_api_config(
     State(state): State<SharedState>,
     Query(query): Query<PostApiConfigQuery>,
     Json(parameters): Json<PostApiConfigBody>,
-) -> impl IntoResponse {
-    tracing::debug!("{parameters:?}");
-
-    let hostname = if query.apply == 1 {
+) -> Result<Json<&'static str>, WasApiError> {
+    tracing::debug!(
+        "post_api_config: type={} hostname={:?}",
+        query.config_type.as_ref(),
         parameters.hostname
-    } else {
-        None
-    };
+    );
+
+    let apply = query.apply == 1;
+    if apply
+        && parameters.hostname.is_none()
+        && !matches!(query.config_type, PostApiConfigType::Was)
+    {
+        return Err(WasApiError::BadRequestError(String::from(
+            "hostname is required to apply config",
+        )));
+    }
 
     match query.config_type {
         PostApiConfigType::Config => {
-            if query.apply == 1 {
-                if let Some(hostname) = hostname {
-                    tracing::debug!("applying config to {hostname}");
-                    let msg_tx = state.get_msg_tx_by_hostname(&hostname).await.unwrap();
-                    let msg = WillowMsgConfig {
-                        config: state.db_pool().get_willow_config().await.unwrap(),
-                    };
-                    let msg = serde_json::to_string_pretty(&msg).unwrap();
-                    msg_tx.send(msg.into()).await.unwrap();
-                }
+            if let (true, Some(hostname)) = (apply, &parameters.hostname) {
+                tracing::debug!("applying config to {hostname}");
+                let msg = WillowMsgConfig {
+                    config: state.db_pool().get_willow_config().await?,
+                };
+                send_to_client(&state, hostname, &msg).await?;
             } else if let Some(config) = parameters.config {
-                state.db_pool().save_willow_config(&config).await.unwrap();
+                state.db_pool().save_willow_config(&config).await?;
             }
         }
         PostApiConfigType::Nvs => {
-            if query.apply == 1 {
-                if let Some(hostname) = hostname {
-                    tracing::debug!("applying nvs to {hostname}");
-                }
+            if let (true, Some(hostname)) = (apply, &parameters.hostname) {
+                tracing::debug!("applying nvs to {hostname}");
+                let msg = WillowMsgNvs {
+                    config: state.db_pool().get_willow_nvs().await?,
+                };
+                send_to_client(&state, hostname, &msg).await?;
             } else if let Some(nvs) = parameters.config {
-                state.db_pool().save_willow_nvs(&nvs).await.unwrap();
+                state.db_pool().save_willow_nvs(&nvs).await?;
+            }
+        }
+        PostApiConfigType::Was => {
+            if let Some(was) = parameters.config {
+                state
+                    .db_pool()
+                    .save_was_config(&was)
+                    .await
+                    .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
             }
         }
-        PostApiConfigType::Was => todo!("was config not implemented"),
     }
 
-    Json("success").into_response()
+    Ok(Json("success"))
+}
+
+async fn send_to_client<T: Serialize>(
+    state: &SharedState,
+    hostname: &str,
+    msg: &T,
+) -> Result<(), WasApiError> {
+    let client_id = state
+        .get_client_id_by_hostname(hostname)
+        .await
+        .map_err(|e| WasApiError::NotFoundError(e.to_string()))?;
+    require_approved(state, client_id)
+        .await
+        .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+    // config and NVS contain secrets
+    require_authenticated(state, client_id)
+        .await
+        .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+
+    let msg_tx = state
+        .get_msg_tx_by_client_id(client_id)
+        .await
+        .map_err(|e| WasApiError::NotFoundError(e.to_string()))?;
+
+    let msg = serde_json::to_string_pretty(msg).context("failed to serialize message")?;
+
+    msg_tx
+        .send(msg.into())
+        .await
+        .context(format!("failed to send message to client {hostname}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        Json,
+        extract::{Query, State},
+    };
+    use serde_json::{Value, json};
+
+    use super::{PostApiConfigBody, PostApiConfigQuery, PostApiConfigType, post_api_config};
+    use crate::{
+        enrollment::enroll_client,
+        error::WasApiError,
+        state::{SharedState, connect_test_client, create_test_state},
+        willow::client::WillowClient,
+    };
+
+    const MAC_ADDR: &str = "7c:df:a1:e7:a8:98";
+
+    async fn apply_nvs(state: &SharedState, hostname: &str) -> Result<(), WasApiError> {
+        post_api_config(
+            State(Arc::clone(state)),
+            Query(PostApiConfigQuery {
+                apply: 1,
+                config_type: PostApiConfigType::Nvs,
+            }),
+            Json(PostApiConfigBody {
+                config: None,
+                hostname: Some(String::from(hostname)),
+            }),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_apply_nvs() {
+        let state = create_test_state().await;
+        state
+            .db_pool()
+            .save_willow_nvs(
+                &json!({"WAS": {"URL": "ws://was"}, "WIFI": {"PSK": "psk", "SSID": "ssid"}}),
+            )
+            .await
+            .expect("failed to save NVS");
+
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_hostname(String::from("willow-kitchen"));
+        let (client_id, mut msg_rx) = connect_test_client(&state, client).await;
+        enroll_client(&state, client_id, MAC_ADDR)
+            .await
+            .expect("failed to enroll client");
+
+        apply_nvs(&state, "willow-kitchen")
+            .await
+            .expect("failed to apply NVS");
+        let msg = msg_rx.try_recv().expect("failed to receive NVS");
+        let msg: Value =
+            serde_json::from_str(msg.to_text().expect("invalid message")).expect("invalid JSON");
+        assert_eq!(msg["config"]["WAS"]["URL"], "ws://was");
+        assert_eq!(msg["config"]["WIFI"]["SSID"], "ssid");
+
+        assert!(matches!(
+            apply_nvs(&state, "willow-unknown").await,
+            Err(WasApiError::NotFoundError(_))
+        ));
+
+        // delivery failures are reported
+        drop(msg_rx);
+        assert!(matches!(
+            apply_nvs(&state, "willow-kitchen").await,
+            Err(WasApiError::InternalServerError(_))
+        ));
+    }
 }
@@ -1,25 +1,187 @@
 use std::sync::Arc;
 
-use axum::Router;
+use auth::auth_routes;
+use axum::{Router, middleware};
 use client::client_routes;
 use config::config_routes;
 use info::info_routes;
+use ota::ota_routes;
 use release::release_routes;
+use rollout::rollout_routes;
 use status::status_routes;
 
-use crate::state::SharedState;
+use crate::{auth::require_auth, state::SharedState};
 
+pub mod auth;
 pub mod client;
 pub mod config;
 pub mod info;
+pub mod ota;
 pub mod release;
+pub mod rollout;
 pub mod status;
 
+/// All API routes require authentication, except for authentication itself and OTA downloads by devices.
 pub fn api_routes(state: &SharedState) -> Router<()> {
     Router::new()
         .nest("/client", client_routes(Arc::clone(state)))
         .nest("/config", config_routes(Arc::clone(state)))
         .nest("/info", info_routes())
         .nest("/release", release_routes(Arc::clone(state)))
+        .nest("/rollout", rollout_routes(Arc::clone(state)))
         .nest("/status", status_routes(Arc::clone(state)))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(state),
+            require_auth,
+        ))
+        .nest("/auth", auth_routes(Arc::clone(state)))
+        .nest("/ota", ota_routes(Arc::clone(state)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::Body,
+        http::{
+            Request, StatusCode,
+            header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, SET_COOKIE},
+        },
+        response::Response,
+    };
+    use tower::ServiceExt;
+
+    use super::api_routes;
+    use crate::{auth::hash_token, db::auth::WillowApiToken, state::create_test_state};
+
+    async fn send(router: &Router, request: Request<Body>) -> Response {
+        router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("failed to send request")
+    }
+
+    fn get(uri: &str, header: Option<(&str, &str)>) -> Request<Body> {
+        let mut request = Request::get(uri);
+        if let Some((name, value)) = header {
+            request = request.header(name, value);
+        }
+
+        request
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    fn setup(username: &str) -> Request<Body> {
+        Request::post("/api/auth/setup")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(
+                r#"{{"username": "{username}", "password": "hunter42"}}"#
+            )))
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_api_auth() {
+        let state = create_test_state().await;
+        let router = Router::new().nest("/api", api_routes(&state));
+
+        for uri in [
+            "/api/auth/token",
+            "/api/auth/user",
+            "/api/client",
+            "/api/client/enrollment",
+            "/api/client/ota",
+            "/api/config?type=was",
+            "/api/info",
+            "/api/release",
+            "/api/rollout",
+            "/api/status",
+        ] {
+            let response = send(&router, get(uri, None)).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+
+        // authentication and OTA downloads by devices are reachable without authentication
+        let response = send(&router, get("/api/ota/9.9.9/ESP32-S3-BOX.bin", None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        // known releases are not downloaded on request, only when WAS starts an OTA
+        let response = send(&router, get("/api/ota/0.3.1/ESP32-S3-BOX.bin", None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = send(&router, setup("admin")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .expect("no session cookie")
+            .to_string();
+
+        let response = send(&router, setup("other")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = send(&router, get("/api/info", Some((COOKIE.as_str(), &cookie)))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state
+            .db_pool()
+            .save_api_token(
+                &WillowApiToken {
+                    id: String::from("token"),
+                    name: String::from("test"),
+                    username: String::from("admin"),
+                    created_at: 0,
+                    last_used_at: None,
+                },
+                &hash_token("api-token"),
+            )
+            .await
+            .expect("failed to save API token");
+        let response = send(
+            &router,
+            get(
+                "/api/info",
+                Some((AUTHORIZATION.as_str(), "Bearer api-token")),
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(
+            &router,
+            get(
+                "/api/info",
+                Some((AUTHORIZATION.as_str(), "Bearer wrong-token")),
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_api_auth_setup_concurrent() {
+        let state = create_test_state().await;
+        let router = Router::new().nest("/api", api_routes(&state));
+
+        let responses = futures_util::future::join_all(
+            (0..4).map(|i| send(&router, setup(&format!("admin{i}")))),
+        )
+        .await;
+        let created = responses
+            .iter()
+            .filter(|r| r.status() == StatusCode::OK)
+            .count();
+        assert_eq!(created, 1);
+        assert_eq!(
+            state
+                .db_pool()
+                .get_users()
+                .await
+                .expect("failed to get users")
+                .len(),
+            1
+        );
+    }
 }
@@ -0,0 +1,55 @@
+use axum::{
+    Router,
+    body::Body,
+    extract::{Path, State},
+    http::header::{CONTENT_LENGTH, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use reqwest::StatusCode;
+
+use crate::{error::WasApiError, state::SharedState};
+
+pub fn ota_routes(state: SharedState) -> Router<()> {
+    Router::new().route("/{version}/{file}", get(get_api_ota).with_state(state))
+}
+
+async fn get_api_ota(
+    State(state): State<SharedState>,
+    Path((version, file)): Path<(String, String)>,
+) -> Result<Response, WasApiError> {
+    tracing::debug!("GET /api/ota/{version}/{file}");
+
+    let Some(platform) = file.strip_suffix(".bin") else {
+        return Err(WasApiError::NotFoundError(format!(
+            "invalid OTA file {file}"
+        )));
+    };
+
+    state
+        .ota_cache()
+        .path(&version, platform)
+        .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+
+    // builds are cached when WAS starts an OTA, so devices cannot make WAS download arbitrary builds
+    let worker_data = state.worker_data().await;
+    let bytes = match worker_data.ota_asset(&version, platform) {
+        Some(asset) => state.ota_cache().get(&version, platform, asset).await?,
+        None => None,
+    };
+    let Some(bytes) = bytes else {
+        return Err(WasApiError::NotFoundError(format!(
+            "no OTA build of release {version} for platform {platform}"
+        )));
+    };
+
+    Ok((
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, String::from("application/octet-stream")),
+            (CONTENT_LENGTH, bytes.len().to_string()),
+        ],
+        Body::from(bytes),
+    )
+        .into_response())
+}
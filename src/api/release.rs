@@ -1,18 +1,91 @@
-use axum::{Json, Router, extract::State, response::IntoResponse, routing::get};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
 use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 
-use crate::state::SharedState;
+use crate::{
+    error::WasApiError,
+    state::SharedState,
+    willow::{client::WillowClient, release::WillowRelease, worker::refresh_worker_data},
+};
+
+#[derive(Debug, Deserialize)]
+struct GetApiRelease {
+    platform: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiRelease {
+    /// Hostnames of the connected clients running the release.
+    clients: Vec<String>,
+    #[serde(flatten)]
+    release: WillowRelease,
+}
 
 pub fn release_routes(state: SharedState) -> Router<()> {
-    Router::new().route("/", get(get_api_release).with_state(state))
+    Router::new()
+        .route("/", get(get_api_release))
+        .route("/refresh", post(post_api_release_refresh))
+        .with_state(state)
+}
+
+async fn get_api_release(
+    State(state): State<SharedState>,
+    Query(query): Query<GetApiRelease>,
+) -> impl IntoResponse {
+    tracing::debug!("GET /api/release - query: {query:?}");
+    let worker_data = state.worker_data().await;
+    let Some(releases) = worker_data.releases() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let show_prereleases = match state.db_pool().get_willow_config().await {
+        Ok(config) => config.show_prereleases(),
+        Err(e) => {
+            tracing::debug!("failed to get Willow config, hiding prereleases: {e}");
+            false
+        }
+    };
+    let clients: Vec<WillowClient> = state.clients().read().await.values().cloned().collect();
+
+    let releases: Vec<ApiRelease> = releases
+        .iter()
+        .filter(|r| show_prereleases || !r.prerelease)
+        .filter_map(|r| {
+            let mut release = r.clone();
+            if let Some(platform) = &query.platform {
+                release
+                    .assets
+                    .retain(|a| a.platform.as_ref() == Some(platform));
+                if release.assets.is_empty() {
+                    return None;
+                }
+            }
+
+            let clients = clients
+                .iter()
+                .filter(|c| release.is_version(c.version()))
+                .filter_map(|c| c.hostname().clone())
+                .collect();
+
+            Some(ApiRelease { clients, release })
+        })
+        .collect();
+
+    Json(releases).into_response()
 }
 
-async fn get_api_release(State(state): State<SharedState>) -> impl IntoResponse {
-    tracing::debug!("GET /api/release");
-    let worker_data = state.worker_data();
-    if let Some(releases) = worker_data.releases() {
-        return Json(releases).into_response();
-    }
+async fn post_api_release_refresh(
+    State(state): State<SharedState>,
+) -> Result<Json<&'static str>, WasApiError> {
+    tracing::debug!("POST /api/release/refresh");
+    refresh_worker_data(&state)
+        .await
+        .map_err(|e| WasApiError::InternalServerError(format!("{e:#}")))?;
 
-    StatusCode::NOT_FOUND.into_response()
+    Ok(Json("success"))
 }
@@ -0,0 +1,65 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::WasApiError,
+    rollout::{RolloutRequest, cancel_rollout, start_rollout},
+    state::SharedState,
+};
+
+#[derive(Debug, Deserialize)]
+struct ApiPostRollout {
+    action: ApiRolloutAction,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ApiRolloutAction {
+    Cancel,
+    Start,
+}
+
+pub fn rollout_routes(state: SharedState) -> Router<()> {
+    Router::new()
+        .route("/", get(get_api_rollout))
+        .route("/", post(post_api_rollout))
+        .with_state(state)
+}
+
+async fn get_api_rollout(State(state): State<SharedState>) -> impl IntoResponse {
+    tracing::debug!("GET /api/rollout");
+    Json(state.rollout().read().await.clone())
+}
+
+async fn post_api_rollout(
+    State(state): State<SharedState>,
+    query: Query<ApiPostRollout>,
+    request: Option<Json<RolloutRequest>>,
+) -> Result<impl IntoResponse, WasApiError> {
+    tracing::debug!("POST /api/rollout - query: {query:?}, request: {request:?}");
+
+    match query.action {
+        ApiRolloutAction::Cancel => {
+            cancel_rollout(&state)
+                .await
+                .map_err(|e| WasApiError::NotFoundError(e.to_string()))?;
+            Ok(Json(state.rollout().read().await.clone()))
+        }
+        ApiRolloutAction::Start => {
+            let Some(Json(request)) = request else {
+                return Err(WasApiError::BadRequestError(String::from(
+                    "rollout request is required",
+                )));
+            };
+            let rollout = start_rollout(&state, request)
+                .await
+                .map_err(|e| WasApiError::BadRequestError(e.to_string()))?;
+            Ok(Json(Some(rollout)))
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHasher, PasswordVerifier, phc::PasswordHash},
+};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, header::AUTHORIZATION},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::CookieJar;
+use sha2::{Digest, Sha256};
+
+use crate::{error::WasApiError, state::SharedState, util::now_ms};
+
+/// Name of the cookie holding the session token of the admin UI.
+pub const SESSION_COOKIE: &str = "was_session";
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const TOKEN_BYTES: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthMethod {
+    ApiToken,
+    Session,
+}
+
+/// User a request was authenticated as, added to the request extensions by [`require_auth`].
+#[derive(Clone, Debug)]
+pub struct AuthUser {
+    pub method: AuthMethod,
+    pub username: String,
+}
+
+/// # Errors
+/// - if hashing the password fails
+pub async fn hash_password(password: String) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        Argon2::default()
+            .hash_password(password.as_bytes())
+            .map(|h| h.to_string())
+            .map_err(|e| anyhow!("failed to hash password: {e}"))
+    })
+    .await?
+}
+
+/// Check `password` against a hash created by [`hash_password`].
+pub async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Generate a random token for sessions and API tokens.
+///
+/// # Errors
+/// - if the system random number generator fails
+pub fn generate_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow!("failed to generate token: {e}"))?;
+
+    Ok(hex::encode(bytes))
+}
+
+/// Tokens are only stored as hash, so a leaked database does not give access to the API.
+#[must_use]
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Create a session for `username` and return its token.
+///
+/// # Errors
+/// - if generating the token fails
+/// - if we fail to save the session in the database
+pub async fn create_session(state: &SharedState, username: &str) -> anyhow::Result<String> {
+    let token = generate_token()?;
+    let now = now_ms();
+    let expires_at = now + i64::try_from(SESSION_LIFETIME.as_millis())?;
+
+    state
+        .db_pool()
+        .save_session(&hash_token(&token), username, now, expires_at)
+        .await?;
+
+    Ok(token)
+}
+
+/// Authenticate a request by bearer API token in the `Authorization` header, or by session cookie.
+///
+/// # Errors
+/// - if we fail to look up the token or session in the database
+pub async fn authenticate(
+    state: &SharedState,
+    headers: &HeaderMap,
+) -> anyhow::Result<Option<AuthUser>> {
+    let now = now_ms();
+
+    if let Some(token) = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        let username = state
+            .db_pool()
+            .get_api_token_user(&hash_token(token.trim()), now)
+            .await?;
+        return Ok(username.map(|username| AuthUser {
+            method: AuthMethod::ApiToken,
+            username,
+        }));
+    }
+
+    if let Some(cookie) = CookieJar::from_headers(headers).get(SESSION_COOKIE) {
+        let username = state
+            .db_pool()
+            .get_session_user(&hash_token(cookie.value()), now)
+            .await?;
+        return Ok(username.map(|username| AuthUser {
+            method: AuthMethod::Session,
+            username,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Middleware rejecting requests that are not authenticated with an API token or session.
+///
+/// # Errors
+/// - if the request is not authenticated
+/// - if we fail to look up the token or session in the database
+pub async fn require_auth(
+    State(state): State<SharedState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, WasApiError> {
+    let Some(user) = authenticate(&state, request.headers()).await? else {
+        return Err(WasApiError::UnauthorizedError(String::from(
+            "authentication required",
+        )));
+    };
+
+    tracing::debug!(
+        "request authenticated as {} by {:?}",
+        user.username,
+        user.method
+    );
+    request.extensions_mut().insert(user);
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token, hash_password, hash_token, verify_password};
+
+    #[tokio::test]
+    async fn test_hash_password() {
+        let hash = hash_password(String::from("hunter42"))
+            .await
+            .expect("failed to hash password");
+
+        assert!(verify_password(String::from("hunter42"), hash.clone()).await);
+        assert!(!verify_password(String::from("hunter43"), hash).await);
+        assert!(!verify_password(String::from("hunter42"), String::from("invalid")).await);
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token().expect("failed to generate token");
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token().expect("failed to generate token"));
+        assert_eq!(hash_token(&token), hash_token(&token));
+    }
+}
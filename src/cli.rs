@@ -0,0 +1,248 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
+
+const DEFAULT_CORS_HEADERS: &[&str] = &["authorization", "content-type"];
+const DEFAULT_CORS_METHODS: &[&str] = &["GET", "POST"];
+const DEFAULT_CORS_ORIGINS: &[&str] = &["http://localhost:3000"];
+const DEFAULT_LISTEN: &str = "[::]:8502";
+const DEFAULT_SECRET_KEY_FILE: &str = "was.key";
+
+/// Willow Application Server
+#[derive(Debug, Parser)]
+#[command(about, version)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// TOML file with server settings, overridden by command line arguments and environment variables
+    #[arg(long, env = "WAS_CONFIG_FILE")]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    server: ServerArgs,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Import config, clients and notifications from the Python Willow Application Server, then exit
+    Import(ImportArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ImportArgs {
+    /// SQLite database of the Python WAS
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+    /// Storage directory of the Python WAS with legacy user_config.json and user_nvs.json files
+    #[arg(long)]
+    pub storage: Option<PathBuf>,
+}
+
+/// Server settings, from the command line, environment or config file.
+#[derive(Args, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerArgs {
+    /// Request headers allowed in CORS requests, comma separated or `*` for any [default: authorization,content-type]
+    #[arg(long, env = "WAS_CORS_HEADERS", value_delimiter = ',')]
+    cors_headers: Vec<String>,
+    /// Methods allowed in CORS requests, comma separated or `*` for any [default: GET,POST]
+    #[arg(long, env = "WAS_CORS_METHODS", value_delimiter = ',')]
+    cors_methods: Vec<String>,
+    /// Origins allowed to make CORS requests, comma separated or `*` for any [default: http://localhost:3000]
+    #[arg(long, env = "WAS_CORS_ORIGINS", value_delimiter = ',')]
+    cors_origins: Vec<String>,
+    /// Allow CORS requests from any origin with any method and headers, for development only
+    #[arg(long, env = "WAS_CORS_PERMISSIVE")]
+    cors_permissive: bool,
+    /// Address and port to listen on, can be repeated or comma separated [default: [::]:8502]
+    #[arg(long, env = "WAS_LISTEN", value_delimiter = ',')]
+    listen: Vec<SocketAddr>,
+    /// File with the key to encrypt secrets in the database, created if it does not exist [default: was.key]
+    #[arg(long, env = "WAS_SECRET_KEY_FILE")]
+    secret_key_file: Option<PathBuf>,
+    /// PEM file with the TLS certificate chain, enables HTTPS and WSS
+    #[arg(long, env = "WAS_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+    /// PEM file with the TLS private key
+    #[arg(long, env = "WAS_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+}
+
+impl ServerArgs {
+    /// Use settings from `other` for all settings not set in `self`.
+    fn or(self, other: Self) -> Self {
+        fn or_vec<T>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
+            if a.is_empty() { b } else { a }
+        }
+
+        Self {
+            cors_headers: or_vec(self.cors_headers, other.cors_headers),
+            cors_methods: or_vec(self.cors_methods, other.cors_methods),
+            cors_origins: or_vec(self.cors_origins, other.cors_origins),
+            cors_permissive: self.cors_permissive || other.cors_permissive,
+            listen: or_vec(self.listen, other.listen),
+            secret_key_file: self.secret_key_file.or(other.secret_key_file),
+            tls_cert: self.tls_cert.or(other.tls_cert),
+            tls_key: self.tls_key.or(other.tls_key),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CorsConfig {
+    pub headers: Vec<String>,
+    pub methods: Vec<String>,
+    pub origins: Vec<String>,
+    pub permissive: bool,
+}
+
+#[derive(Debug)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct ServerConfig {
+    cors: CorsConfig,
+    listen: Vec<SocketAddr>,
+    secret_key_file: PathBuf,
+    tls: Option<TlsConfig>,
+}
+
+impl ServerConfig {
+    #[must_use]
+    pub fn cors(&self) -> &CorsConfig {
+        &self.cors
+    }
+
+    #[must_use]
+    pub fn listen(&self) -> &[SocketAddr] {
+        &self.listen
+    }
+
+    #[must_use]
+    pub fn secret_key_file(&self) -> &Path {
+        &self.secret_key_file
+    }
+
+    #[must_use]
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+}
+
+impl Cli {
+    #[must_use]
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
+    /// Merge server settings from the command line and environment with those from the config file.
+    ///
+    /// # Errors
+    /// - if the config file cannot be read or parsed
+    /// - if only one of the TLS certificate and key is set
+    pub fn server_config(self) -> anyhow::Result<ServerConfig> {
+        let args = match &self.config {
+            Some(path) => self.server.or(read_config_file(path)?),
+            None => self.server,
+        };
+
+        let or_default = |values: Vec<String>, default: &[&str]| {
+            if values.is_empty() {
+                default.iter().map(ToString::to_string).collect()
+            } else {
+                values
+            }
+        };
+        let cors = CorsConfig {
+            headers: or_default(args.cors_headers, DEFAULT_CORS_HEADERS),
+            methods: or_default(args.cors_methods, DEFAULT_CORS_METHODS),
+            origins: or_default(args.cors_origins, DEFAULT_CORS_ORIGINS),
+            permissive: args.cors_permissive,
+        };
+
+        let listen = if args.listen.is_empty() {
+            vec![DEFAULT_LISTEN.parse()?]
+        } else {
+            args.listen
+        };
+
+        let secret_key_file = args
+            .secret_key_file
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SECRET_KEY_FILE));
+
+        let tls = match (args.tls_cert, args.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsConfig { cert, key }),
+            (None, None) => None,
+            _ => return Err(anyhow!("tls_cert and tls_key must be set together")),
+        };
+
+        Ok(ServerConfig {
+            cors,
+            listen,
+            secret_key_file,
+            tls,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> anyhow::Result<ServerArgs> {
+    let content = fs::read_to_string(path)
+        .context(format!("failed to read config file {}", path.display()))?;
+
+    toml::from_str(&content).context(format!("invalid config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Cli, ServerArgs};
+
+    #[test]
+    fn test_server_config() {
+        let file: ServerArgs = toml::from_str(
+            r#"
+            listen = ["0.0.0.0:8502", "[::1]:8443"]
+            tls_cert = "/etc/was/cert.pem"
+            tls_key = "/etc/was/key.pem"
+            "#,
+        )
+        .expect("failed to parse config file");
+
+        let listen = vec!["127.0.0.1:9000".parse().expect("failed to parse address")];
+        let args = ServerArgs {
+            listen: listen.clone(),
+            ..Default::default()
+        }
+        .or(file);
+        assert_eq!(args.listen, listen);
+        assert_eq!(args.tls_cert, Some(PathBuf::from("/etc/was/cert.pem")));
+
+        let cli = Cli {
+            command: None,
+            config: None,
+            server: ServerArgs::default(),
+        };
+        let config = cli.server_config().expect("failed to get server config");
+        assert_eq!(config.listen().len(), 1);
+        assert!(config.tls().is_none());
+
+        let cli = Cli {
+            command: None,
+            config: None,
+            server: ServerArgs {
+                tls_cert: Some(PathBuf::from("cert.pem")),
+                ..Default::default()
+            },
+        };
+        assert!(cli.server_config().is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::willow::config::{deserialize_string_to_bool, deserialize_string_to_number};
+
+const DEFAULT_COMMAND_ENDPOINT_TIMEOUT: u64 = 10;
+const DEFAULT_WAKE_WINDOW: u64 = 400;
+const DEFAULT_WORKER_REFRESH_INTERVAL: u64 = 3600;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WasOtaSource {
+    /// Devices download OTA builds from the WAS OTA cache.
+    #[default]
+    Was,
+    /// Devices download OTA builds directly from the release URL.
+    Upstream,
+}
+
+/// Server-side settings, stored in `willow_config` with `config_type` 'was'.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasConfig {
+    /// Timeout for requests to the command endpoint, in seconds.
+    #[serde(deserialize_with = "deserialize_string_to_number")]
+    command_endpoint_timeout: u64,
+    /// Hold devices with unknown MAC addresses until they are approved, and require their device token.
+    #[serde(deserialize_with = "deserialize_string_to_bool")]
+    device_enrollment: bool,
+    /// MQTT topic to wait for a response on after publishing a command, replies go to `<topic>/<id>` with the id
+    /// of the command.
+    mqtt_response_topic: Option<String>,
+    /// Where devices download OTA builds from.
+    ota_source: WasOtaSource,
+    /// Time to collect `wake_start` events from all clients before picking a winner, in milliseconds.
+    #[serde(deserialize_with = "deserialize_string_to_number")]
+    wake_window: u64,
+    /// Interval to refresh releases and default config from the Willow worker, in seconds, 0 disables refreshing.
+    #[serde(deserialize_with = "deserialize_string_to_number")]
+    worker_refresh_interval: u64,
+}
+
+impl Default for WasConfig {
+    fn default() -> Self {
+        Self {
+            command_endpoint_timeout: DEFAULT_COMMAND_ENDPOINT_TIMEOUT,
+            device_enrollment: false,
+            mqtt_response_topic: None,
+            ota_source: WasOtaSource::default(),
+            wake_window: DEFAULT_WAKE_WINDOW,
+            worker_refresh_interval: DEFAULT_WORKER_REFRESH_INTERVAL,
+        }
+    }
+}
+
+impl WasConfig {
+    #[must_use]
+    pub fn command_endpoint_timeout(&self) -> Duration {
+        Duration::from_secs(self.command_endpoint_timeout)
+    }
+
+    #[must_use]
+    pub fn device_enrollment(&self) -> bool {
+        self.device_enrollment
+    }
+
+    #[must_use]
+    pub fn mqtt_response_topic(&self) -> &Option<String> {
+        &self.mqtt_response_topic
+    }
+
+    #[must_use]
+    pub fn ota_source(&self) -> WasOtaSource {
+        self.ota_source
+    }
+
+    #[must_use]
+    pub fn wake_window(&self) -> Duration {
+        Duration::from_millis(self.wake_window)
+    }
+
+    #[must_use]
+    pub fn worker_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.worker_refresh_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::WasConfig;
+
+    #[test]
+    fn test_deserialize_was_config() {
+        let config: WasConfig =
+            serde_json::from_str("{}").expect("failed to deserialize empty WAS config");
+        assert_eq!(config.wake_window(), Duration::from_millis(400));
+        assert!(!config.device_enrollment());
+
+        let config: WasConfig =
+            serde_json::from_str(r#"{"wake_window": "250", "mqtt_response_topic": "was/reply"}"#)
+                .expect("failed to deserialize WAS config");
+        assert_eq!(config.wake_window(), Duration::from_millis(250));
+        assert_eq!(config.mqtt_response_topic().as_deref(), Some("was/reply"));
+
+        let config: WasConfig = serde_json::from_str(r#"{"device_enrollment": "true"}"#)
+            .expect("failed to deserialize WAS config");
+        assert!(config.device_enrollment());
+
+        assert!(serde_json::from_str::<WasConfig>(r#"{"foo": "bar"}"#).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{Any, FromRow, query_as};
+
+use super::pool::Pool;
+
+#[derive(Debug, FromRow)]
+struct WillowUserRow {
+    password_hash: String,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct WillowUser {
+    pub username: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct WillowUsernameRow {
+    username: String,
+}
+
+/// API token without the token itself, which is only stored as hash.
+#[derive(Debug, FromRow, Serialize)]
+pub struct WillowApiToken {
+    pub id: String,
+    pub name: String,
+    pub username: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+impl Pool {
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_users(&self) -> Result<Vec<WillowUser>> {
+        tracing::debug!("get_users");
+
+        let rows = query_as::<Any, WillowUser>(
+            "SELECT username, created_at FROM willow_users ORDER BY username",
+        )
+        .fetch_all(self.get())
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_user_password_hash(&self, username: &str) -> Result<Option<String>> {
+        tracing::debug!("get_user_password_hash: {username}");
+
+        let row = query_as::<Any, WillowUserRow>(
+            "SELECT password_hash FROM willow_users WHERE username = $1",
+        )
+        .bind(username)
+        .fetch_optional(self.get())
+        .await?;
+
+        Ok(row.map(|r| r.password_hash))
+    }
+
+    /// Delete a user including its sessions and API tokens.
+    ///
+    /// # Errors
+    /// - if any DELETE query fails
+    pub async fn delete_user(&self, username: &str) -> Result<()> {
+        tracing::debug!("delete_user: {username}");
+
+        let mut tx = self.get().begin().await?;
+        for table in ["willow_api_tokens", "willow_sessions", "willow_users"] {
+            sqlx::query::<Any>(&format!("DELETE FROM {table} WHERE username = $1"))
+                .bind(username)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Create a user, or set the password of an existing user.
+    ///
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_user(&self, username: &str, password_hash: &str, now: i64) -> Result<()> {
+        tracing::debug!("save_user: {username}");
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_users (username, password_hash, created_at) VALUES ($1, $2, $3)
+                    ON CONFLICT(username) DO UPDATE SET password_hash = excluded.password_hash",
+        )
+        .bind(username)
+        .bind(password_hash)
+        .bind(now)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Create the first user, checking that no users exist in the same statement so concurrent calls cannot both
+    /// create a user. Returns whether the user was created.
+    ///
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_first_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+        now: i64,
+    ) -> Result<bool> {
+        tracing::debug!("save_first_user: {username}");
+
+        let result = sqlx::query::<Any>(
+            "INSERT INTO willow_users (username, password_hash, created_at)
+                    SELECT $1, $2, $3 WHERE NOT EXISTS (SELECT 1 FROM willow_users)",
+        )
+        .bind(username)
+        .bind(password_hash)
+        .bind(now)
+        .execute(self.get())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get the user of a session that has not expired at `now`.
+    ///
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_session_user(&self, token_hash: &str, now: i64) -> Result<Option<String>> {
+        let row = query_as::<Any, WillowUsernameRow>(
+            "SELECT username FROM willow_sessions WHERE token_hash = $1 AND expires_at > $2",
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(self.get())
+        .await?;
+
+        Ok(row.map(|r| r.username))
+    }
+
+    /// # Errors
+    /// - if DELETE query fails
+    pub async fn delete_session(&self, token_hash: &str) -> Result<()> {
+        sqlx::query::<Any>("DELETE FROM willow_sessions WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(self.get())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Save a new session, and delete sessions that expired before `now`.
+    ///
+    /// # Errors
+    /// - if INSERT or DELETE query fails
+    pub async fn save_session(
+        &self,
+        token_hash: &str,
+        username: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<()> {
+        tracing::debug!("save_session: {username}");
+
+        sqlx::query::<Any>("DELETE FROM willow_sessions WHERE expires_at <= $1")
+            .bind(now)
+            .execute(self.get())
+            .await?;
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_sessions (token_hash, username, created_at, expires_at)
+                    VALUES ($1, $2, $3, $4)",
+        )
+        .bind(token_hash)
+        .bind(username)
+        .bind(now)
+        .bind(expires_at)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_api_tokens(&self) -> Result<Vec<WillowApiToken>> {
+        tracing::debug!("get_api_tokens");
+
+        let rows = query_as::<Any, WillowApiToken>(
+            "SELECT id, name, username, created_at, last_used_at FROM willow_api_tokens
+                    ORDER BY created_at",
+        )
+        .fetch_all(self.get())
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Get the user of an API token, and record that the token was used at `now`.
+    ///
+    /// # Errors
+    /// - if SELECT or UPDATE query fails
+    pub async fn get_api_token_user(&self, token_hash: &str, now: i64) -> Result<Option<String>> {
+        let row = query_as::<Any, WillowUsernameRow>(
+            "SELECT username FROM willow_api_tokens WHERE token_hash = $1",
+        )
+        .bind(token_hash)
+        .fetch_optional(self.get())
+        .await?;
+
+        if row.is_some() {
+            sqlx::query::<Any>(
+                "UPDATE willow_api_tokens SET last_used_at = $1 WHERE token_hash = $2",
+            )
+            .bind(now)
+            .bind(token_hash)
+            .execute(self.get())
+            .await?;
+        }
+
+        Ok(row.map(|r| r.username))
+    }
+
+    /// # Errors
+    /// - if DELETE query fails
+    pub async fn delete_api_token(&self, id: &str) -> Result<bool> {
+        tracing::debug!("delete_api_token: {id}");
+
+        let result = sqlx::query::<Any>("DELETE FROM willow_api_tokens WHERE id = $1")
+            .bind(id)
+            .execute(self.get())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_api_token(&self, token: &WillowApiToken, token_hash: &str) -> Result<()> {
+        tracing::debug!("save_api_token: {} for {}", token.name, token.username);
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_api_tokens (id, name, token_hash, username, created_at)
+                    VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&token.id)
+        .bind(&token.name)
+        .bind(token_hash)
+        .bind(&token.username)
+        .bind(token.created_at)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{Any, AnyConnection, FromRow, query_as};
+
+use super::pool::Pool;
+use crate::willow::client::WillowClientStatus;
+
+#[derive(Debug, FromRow)]
+struct WillowClientRow {
+    label: String,
+}
+
+#[derive(Debug, FromRow)]
+struct WillowClientEnrollmentRow {
+    status: String,
+    token_hash: Option<String>,
+}
+
+/// Client known to WAS, whether it is connected or not.
+#[derive(Debug, FromRow, Serialize)]
+pub struct WillowClientInfo {
+    pub mac_addr: String,
+    pub label: String,
+    pub status: String,
+}
+
+impl Pool {
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_willow_client_label(&self, mac_addr: &str) -> Result<Option<String>> {
+        tracing::debug!("get_willow_client_label: {mac_addr}");
+
+        let row = query_as::<Any, WillowClientRow>(
+            "SELECT label FROM willow_clients WHERE mac_addr = $1",
+        )
+        .bind(mac_addr)
+        .fetch_optional(self.get())
+        .await?;
+
+        Ok(row.map(|r| r.label).filter(|l| !l.is_empty()))
+    }
+
+    /// Insert a client with an empty label if no client with the MAC address exists yet.
+    ///
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_willow_client(&self, mac_addr: &str) -> Result<()> {
+        tracing::debug!("save_willow_client: {mac_addr}");
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_clients (mac_addr, label) VALUES ($1, '')
+                    ON CONFLICT(mac_addr) DO NOTHING",
+        )
+        .bind(mac_addr)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_willow_client_label(&self, mac_addr: &str, label: &str) -> Result<()> {
+        let mut conn = self.get().acquire().await?;
+        self.save_willow_client_label_tx(&mut conn, mac_addr, label)
+            .await
+    }
+
+    /// Save a client label in the transaction `tx`.
+    ///
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_willow_client_label_tx(
+        &self,
+        tx: &mut AnyConnection,
+        mac_addr: &str,
+        label: &str,
+    ) -> Result<()> {
+        tracing::debug!("save_willow_client_label: {mac_addr} -> {label}");
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_clients (mac_addr, label) VALUES ($1, $2)
+                    ON CONFLICT(mac_addr) DO UPDATE SET label = excluded.label",
+        )
+        .bind(mac_addr)
+        .bind(label)
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_willow_clients(&self) -> Result<Vec<WillowClientInfo>> {
+        tracing::debug!("get_willow_clients");
+
+        let rows = query_as::<Any, WillowClientInfo>(
+            "SELECT mac_addr, label, status FROM willow_clients ORDER BY mac_addr",
+        )
+        .fetch_all(self.get())
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Get the enrollment status and device token hash of a client.
+    ///
+    /// # Errors
+    /// - if SELECT query fails
+    /// - if the stored status is invalid
+    pub async fn get_willow_client_enrollment(
+        &self,
+        mac_addr: &str,
+    ) -> Result<Option<(WillowClientStatus, Option<String>)>> {
+        tracing::debug!("get_willow_client_enrollment: {mac_addr}");
+
+        let row = query_as::<Any, WillowClientEnrollmentRow>(
+            "SELECT status, token_hash FROM willow_clients WHERE mac_addr = $1",
+        )
+        .bind(mac_addr)
+        .fetch_optional(self.get())
+        .await?;
+
+        row.map(|r| Ok((WillowClientStatus::from_str(&r.status)?, r.token_hash)))
+            .transpose()
+    }
+
+    /// Save the enrollment status and device token hash of a client, creating the client if needed.
+    ///
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_willow_client_enrollment(
+        &self,
+        mac_addr: &str,
+        status: WillowClientStatus,
+        token_hash: Option<&str>,
+    ) -> Result<()> {
+        tracing::debug!(
+            "save_willow_client_enrollment: {mac_addr} -> {}",
+            status.as_ref()
+        );
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_clients (mac_addr, label, status, token_hash) VALUES ($1, '', $2, $3)
+                    ON CONFLICT(mac_addr) DO UPDATE
+                    SET status = excluded.status, token_hash = excluded.token_hash",
+        )
+        .bind(mac_addr)
+        .bind(status.as_ref())
+        .bind(token_hash)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{db::pool::create_test_pool, willow::client::WillowClientStatus};
+
+    #[tokio::test]
+    async fn test_client_enrollment() {
+        let pool = create_test_pool().await;
+        let mac_addr = "24:0a:c4:00:00:01";
+
+        pool.save_willow_client(mac_addr)
+            .await
+            .expect("failed to save client");
+        let enrollment = pool
+            .get_willow_client_enrollment(mac_addr)
+            .await
+            .expect("failed to get enrollment");
+        assert_eq!(enrollment, Some((WillowClientStatus::Pending, None)));
+
+        pool.save_willow_client_label(mac_addr, "kitchen")
+            .await
+            .expect("failed to save label");
+        pool.save_willow_client_enrollment(mac_addr, WillowClientStatus::Approved, Some("hash"))
+            .await
+            .expect("failed to save enrollment");
+        let enrollment = pool
+            .get_willow_client_enrollment(mac_addr)
+            .await
+            .expect("failed to get enrollment");
+        assert_eq!(
+            enrollment,
+            Some((WillowClientStatus::Approved, Some(String::from("hash"))))
+        );
+
+        let clients = pool
+            .get_willow_clients()
+            .await
+            .expect("failed to get clients");
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].label, "kitchen");
+        assert_eq!(clients[0].status, "approved");
+    }
+}
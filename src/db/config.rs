@@ -3,9 +3,13 @@ use std::collections::HashMap;
 
 use anyhow::{Result, anyhow};
 use serde_json::Value;
-use sqlx::{Any, FromRow, query_as};
+use sqlx::{Any, AnyConnection, FromRow, query_as};
 
-use crate::willow::config::{WillowConfig, WillowNvsConfig};
+use crate::{
+    config::WasConfig,
+    secret::{CONFIG_SECRETS, NVS_SECRETS, REDACTED, is_encrypted},
+    willow::config::{WillowConfig, WillowNvsConfig},
+};
 
 use super::pool::Pool;
 
@@ -24,6 +28,15 @@ struct WillowNvsRow {
     config_value: Option<String>,
 }
 
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, FromRow)]
+struct WillowSecretRow {
+    config_type: String,
+    config_namespace: Option<String>,
+    config_name: String,
+    config_value: Option<String>,
+}
+
 impl Pool {
     /// # Errors
     /// - if SELECT query fails
@@ -32,27 +45,54 @@ impl Pool {
     pub async fn get_willow_config(&self) -> Result<WillowConfig> {
         tracing::debug!("get_willow_config");
 
+        let config_map = self.get_config_map("config").await?;
+        let json = serde_json::to_string(&config_map)?;
+        let config: WillowConfig = serde_json::from_str(&json)?;
+
+        Ok(config)
+    }
+
+    /// Get the WAS config, using defaults for all settings that were never saved.
+    ///
+    /// # Errors
+    /// - if SELECT query fails
+    /// - if serializing `config_map` to string fails
+    /// - if deserializing config json string to `WasConfig` fails
+    pub async fn get_was_config(&self) -> Result<WasConfig> {
+        tracing::debug!("get_was_config");
+
+        let config_map = self.get_config_map("was").await?;
+        let json = serde_json::to_string(&config_map)?;
+        let config: WasConfig = serde_json::from_str(&json)?;
+
+        Ok(config)
+    }
+
+    /// Get the WAS config, falling back to the defaults if the stored config is invalid, e.g. because it was saved by
+    /// another version of WAS, so a bad setting does not break voice commands and device connections.
+    pub async fn get_was_config_or_default(&self) -> WasConfig {
+        self.get_was_config().await.unwrap_or_else(|e| {
+            tracing::warn!("failed to get WAS config, using default config: {e}");
+            WasConfig::default()
+        })
+    }
+
+    async fn get_config_map(&self, config_type: &str) -> Result<HashMap<String, String>> {
         let rows = query_as::<Any, WillowConfigRow>(
-            "SELECT config_name, config_value FROM willow_config WHERE config_type = 'config'",
+            "SELECT config_name, config_value FROM willow_config WHERE config_type = $1",
         )
+        .bind(config_type)
         .fetch_all(self.get())
         .await?;
 
-        tracing::debug!("rows: {rows:?}");
-
         let mut config_map: HashMap<String, String> = HashMap::new();
         for row in rows {
             if let Some(value) = row.config_value {
-                config_map.insert(row.config_name, value);
+                config_map.insert(row.config_name, self.decrypt_secret(&value)?);
             };
         }
 
-        tracing::debug!("config_map: {config_map:?}");
-
-        let json = serde_json::to_string(&config_map)?;
-        let config: WillowConfig = serde_json::from_str(&json)?;
-
-        Ok(config)
+        Ok(config_map)
     }
 
     /// # Errors
@@ -67,13 +107,12 @@ impl Pool {
         )
          .fetch_all(self.get()).await?;
 
-        tracing::debug!("rows: {rows:?}");
-
         let mut was_map: HashMap<String, String> = HashMap::new();
         let mut wifi_map: HashMap<String, String> = HashMap::new();
 
         for row in rows {
             if let Some(value) = row.config_value {
+                let value = self.decrypt_secret(&value)?;
                 match row.config_namespace.as_str() {
                     "WAS" => {
                         was_map.insert(row.config_name, value);
@@ -83,7 +122,7 @@ impl Pool {
                     }
                     s => {
                         tracing::warn!(
-                            "database contains NVS record with unknown namespace: namespace='{s}' name='{}' value='{value}'",
+                            "database contains NVS record with unknown namespace: namespace='{s}' name='{}'",
                             row.config_name
                         );
                     }
@@ -91,9 +130,6 @@ impl Pool {
             }
         }
 
-        tracing::debug!("was_map: {was_map:?}");
-        tracing::debug!("wifi_map: {wifi_map:?}");
-
         let was_json = serde_json::to_string(&was_map)?;
         let wifi_json = serde_json::to_string(&wifi_map)?;
 
@@ -108,26 +144,83 @@ impl Pool {
     /// - if we fail to execute a query
     /// - if we fail to commit the db transaction
     pub async fn save_willow_config(&self, config: &Value) -> Result<()> {
+        let mut tx = self.get().begin().await?;
+        self.save_willow_config_tx(&mut tx, config).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Save Willow config in the transaction `tx`.
+    ///
+    /// # Errors
+    /// - if we fail to execute a query
+    pub async fn save_willow_config_tx(
+        &self,
+        tx: &mut AnyConnection,
+        config: &Value,
+    ) -> Result<()> {
+        self.save_config_map(tx, "config", config).await
+    }
+
+    /// # Errors
+    /// - if the config contains unknown settings or invalid values
+    /// - if we fail to start a db transaction
+    /// - if we fail to execute a query
+    /// - if we fail to commit the db transaction
+    pub async fn save_was_config(&self, config: &Value) -> Result<()> {
+        let mut tx = self.get().begin().await?;
+        self.save_was_config_tx(&mut tx, config).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Save WAS config in the transaction `tx`.
+    ///
+    /// # Errors
+    /// - if the config contains unknown settings or invalid values
+    /// - if we fail to execute a query
+    pub async fn save_was_config_tx(&self, tx: &mut AnyConnection, config: &Value) -> Result<()> {
         if let Value::Object(map) = config {
-            let mut tx = self.get().begin().await?;
+            let mut config_map: HashMap<&String, String> = HashMap::new();
+            for (k, v) in map {
+                if let Some(v_str) = value_to_string(v)? {
+                    config_map.insert(k, v_str);
+                }
+            }
+
+            let json = serde_json::to_string(&config_map)?;
+            serde_json::from_str::<WasConfig>(&json)
+                .map_err(|e| anyhow!("invalid WAS config: {e}"))?;
+        }
 
+        self.save_config_map(tx, "was", config).await
+    }
+
+    async fn save_config_map(
+        &self,
+        tx: &mut AnyConnection,
+        config_type: &str,
+        config: &Value,
+    ) -> Result<()> {
+        if let Value::Object(map) = config {
             for (k, v) in map {
-                let v_str: Option<String> = match v {
-                    Value::Bool(b) => Some(b.to_string()),
-                    Value::Null => None,
-                    Value::Number(n) => Some(n.to_string()),
-                    Value::String(s) => Some(s.to_string()),
-                    other => return Err(anyhow!("unsupported value {other:?}")),
-                };
+                let mut v_str = value_to_string(v)?;
+                if config_type == "config" && CONFIG_SECRETS.contains(&k.as_str()) {
+                    if v_str.as_deref() == Some(REDACTED) {
+                        continue;
+                    }
+                    v_str = v_str.map(|v| self.encrypt_secret(&v)).transpose()?;
+                }
 
                 sqlx::query::<Any>(
-                "INSERT INTO willow_config (config_type, config_name, config_value) VALUES ('config', $1, $2)
+                "INSERT INTO willow_config (config_type, config_name, config_value) VALUES ($1, $2, $3)
                         ON CONFLICT(config_type, config_name) DO UPDATE SET config_value = excluded.config_value")
+            .bind(config_type)
             .bind(k)
             .bind(v_str).execute(&mut *tx).await?;
             }
-
-            tx.commit().await?;
         }
 
         Ok(())
@@ -138,25 +231,186 @@ impl Pool {
     /// - if we fail to execute a query
     /// - if we fail to commit the db transaction
     pub async fn save_willow_nvs(&self, config: &Value) -> Result<()> {
-        if let Value::Object(map) = config {
-            let mut tx = self.get().begin().await?;
+        let mut tx = self.get().begin().await?;
+        self.save_willow_nvs_tx(&mut tx, config).await?;
+        tx.commit().await?;
 
+        Ok(())
+    }
+
+    /// Save NVS config in the transaction `tx`.
+    ///
+    /// # Errors
+    /// - if we fail to execute a query
+    pub async fn save_willow_nvs_tx(&self, tx: &mut AnyConnection, config: &Value) -> Result<()> {
+        if let Value::Object(map) = config {
             for (namespace, v) in map {
                 if let Value::Object(map) = v {
                     for (k, v) in map {
+                        let mut v_str = v.as_str().map(ToString::to_string);
+                        if NVS_SECRETS.contains(&(namespace.as_str(), k.as_str())) {
+                            if v_str.as_deref() == Some(REDACTED) {
+                                continue;
+                            }
+                            v_str = v_str.map(|v| self.encrypt_secret(&v)).transpose()?;
+                        }
+
                         sqlx::query::<Any>(
                             "INSERT INTO willow_config (config_type, config_namespace, config_name, config_value) VALUES ('nvs', $1, $2, $3)
                                      ON CONFLICT(config_type, config_name) DO UPDATE SET config_value = excluded.config_value")
                         .bind(namespace)
                         .bind(k)
-                        .bind(v.as_str()).execute(&mut *tx).await?;
+                        .bind(v_str).execute(&mut *tx).await?;
                     }
                 }
             }
+        }
+
+        Ok(())
+    }
 
-            tx.commit().await?;
+    /// Encrypt secrets that were saved as plaintext, e.g. before a secret key was configured.
+    ///
+    /// # Errors
+    /// - if SELECT or UPDATE query fails
+    /// - if encrypting a secret fails
+    pub async fn encrypt_stored_secrets(&self) -> Result<()> {
+        tracing::debug!("encrypt_stored_secrets");
+
+        let rows = query_as::<Any, WillowSecretRow>(
+            "SELECT config_type, config_namespace, config_name, config_value FROM willow_config
+                    WHERE config_type IN ('config', 'nvs')",
+        )
+        .fetch_all(self.get())
+        .await?;
+
+        for row in rows {
+            let secret = match row.config_type.as_str() {
+                "config" => CONFIG_SECRETS.contains(&row.config_name.as_str()),
+                _ => NVS_SECRETS.contains(&(
+                    row.config_namespace.as_deref().unwrap_or_default(),
+                    row.config_name.as_str(),
+                )),
+            };
+            let Some(value) = row.config_value.filter(|v| secret && !is_encrypted(v)) else {
+                continue;
+            };
+
+            sqlx::query::<Any>(
+                "UPDATE willow_config SET config_value = $1 WHERE config_type = $2 AND config_name = $3",
+            )
+            .bind(self.encrypt_secret(&value)?)
+            .bind(&row.config_type)
+            .bind(&row.config_name)
+            .execute(self.get())
+            .await?;
+            tracing::info!("encrypted stored secret {}", row.config_name);
         }
 
         Ok(())
     }
 }
+
+fn value_to_string(v: &Value) -> Result<Option<String>> {
+    match v {
+        Value::Bool(b) => Ok(Some(b.to_string())),
+        Value::Null => Ok(None),
+        Value::Number(n) => Ok(Some(n.to_string())),
+        Value::String(s) => Ok(Some(s.to_string())),
+        other => Err(anyhow!("unsupported value {other:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{
+        config::WasConfig,
+        db::pool::{Pool, create_test_pool},
+        secret::{REDACTED, SecretKey, is_encrypted},
+    };
+
+    async fn create_pool() -> Pool {
+        create_test_pool()
+            .await
+            .with_secret_key(SecretKey::new(&[7u8; 32]).expect("failed to create key"))
+    }
+
+    async fn get_stored_value(pool: &Pool, name: &str) -> String {
+        let (value,): (String,) =
+            sqlx::query_as("SELECT config_value FROM willow_config WHERE config_name = $1")
+                .bind(name)
+                .fetch_one(pool.get())
+                .await
+                .expect("failed to get stored value");
+
+        value
+    }
+
+    #[tokio::test]
+    async fn test_secrets_encrypted() {
+        let pool = create_pool().await;
+
+        pool.save_willow_nvs(
+            &json!({"WAS": {"URL": "ws://was"}, "WIFI": {"PSK": "psk", "SSID": "ssid"}}),
+        )
+        .await
+        .expect("failed to save NVS config");
+        assert!(is_encrypted(&get_stored_value(&pool, "PSK").await));
+        assert_eq!(get_stored_value(&pool, "SSID").await, "ssid");
+
+        pool.save_willow_nvs(&json!({"WIFI": {"PSK": REDACTED, "SSID": "other"}}))
+            .await
+            .expect("failed to save NVS config");
+        let nvs = serde_json::to_value(
+            pool.get_willow_nvs()
+                .await
+                .expect("failed to get NVS config"),
+        )
+        .expect("failed to serialize NVS config");
+        assert_eq!(nvs["WIFI"]["PSK"], "psk");
+        assert_eq!(nvs["WIFI"]["SSID"], "other");
+
+        // secrets saved before encryption are encrypted in place
+        sqlx::query(
+            "INSERT INTO willow_config (config_type, config_name, config_value) VALUES ('config', 'hass_token', 'token')",
+        )
+        .execute(pool.get())
+        .await
+        .expect("failed to insert plaintext secret");
+        pool.encrypt_stored_secrets()
+            .await
+            .expect("failed to encrypt stored secrets");
+        assert!(is_encrypted(&get_stored_value(&pool, "hass_token").await));
+        assert!(!is_encrypted(&get_stored_value(&pool, "URL").await));
+    }
+
+    #[tokio::test]
+    async fn test_was_config_fallback() {
+        let pool = create_pool().await;
+
+        pool.save_was_config(&json!({"wake_window": "250"}))
+            .await
+            .expect("failed to save WAS config");
+        assert_eq!(
+            pool.get_was_config_or_default().await.wake_window(),
+            Duration::from_millis(250)
+        );
+
+        // setting saved by another version of WAS
+        sqlx::query(
+            "INSERT INTO willow_config (config_type, config_name, config_value) VALUES ('was', 'removed_setting', '1')",
+        )
+        .execute(pool.get())
+        .await
+        .expect("failed to insert unknown setting");
+        assert!(pool.get_was_config().await.is_err());
+        assert_eq!(
+            pool.get_was_config_or_default().await.wake_window(),
+            WasConfig::default().wake_window()
+        );
+    }
+}
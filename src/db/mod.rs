@@ -1,2 +1,7 @@
+pub mod auth;
+pub mod client;
 pub mod config;
+pub mod notification;
+pub mod ota;
 pub mod pool;
+pub mod worker;
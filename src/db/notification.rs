@@ -0,0 +1,79 @@
+use anyhow::Result;
+use sqlx::{Any, AnyConnection, FromRow, query_as};
+
+use crate::willow::messages::WillowNotifyData;
+
+use super::pool::Pool;
+
+#[derive(Debug, FromRow)]
+struct WillowNotificationRow {
+    hostname: String,
+    data: String,
+}
+
+impl Pool {
+    /// # Errors
+    /// - if SELECT query fails
+    /// - if deserializing notification data to `WillowNotifyData` fails
+    pub async fn get_notifications(&self) -> Result<Vec<(String, WillowNotifyData)>> {
+        tracing::debug!("get_notifications");
+
+        let rows = query_as::<Any, WillowNotificationRow>(
+            "SELECT hostname, data FROM willow_notifications ORDER BY id",
+        )
+        .fetch_all(self.get())
+        .await?;
+
+        rows.into_iter()
+            .map(|row| Ok((row.hostname, serde_json::from_str(&row.data)?)))
+            .collect()
+    }
+
+    /// # Errors
+    /// - if DELETE query fails
+    pub async fn delete_notification(&self, hostname: &str, id: i64) -> Result<()> {
+        tracing::debug!("delete_notification: {hostname} {id}");
+
+        sqlx::query::<Any>("DELETE FROM willow_notifications WHERE id = $1 AND hostname = $2")
+            .bind(id)
+            .bind(hostname)
+            .execute(self.get())
+            .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    /// - if serializing `data` fails
+    /// - if INSERT query fails
+    pub async fn save_notification(&self, hostname: &str, data: &WillowNotifyData) -> Result<()> {
+        let mut conn = self.get().acquire().await?;
+        self.save_notification_tx(&mut conn, hostname, data).await
+    }
+
+    /// Save a notification in the transaction `tx`.
+    ///
+    /// # Errors
+    /// - if serializing `data` fails
+    /// - if INSERT query fails
+    pub async fn save_notification_tx(
+        &self,
+        tx: &mut AnyConnection,
+        hostname: &str,
+        data: &WillowNotifyData,
+    ) -> Result<()> {
+        tracing::debug!("save_notification: {hostname} {data:?}");
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_notifications (id, hostname, data) VALUES ($1, $2, $3)
+                    ON CONFLICT(id, hostname) DO UPDATE SET data = excluded.data",
+        )
+        .bind(data.id)
+        .bind(hostname)
+        .bind(serde_json::to_string(data)?)
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{Any, FromRow, query_as};
+
+use super::pool::Pool;
+
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct WillowOtaHistory {
+    pub id: String,
+    pub mac_addr: String,
+    pub hostname: Option<String>,
+    pub version_from: String,
+    pub version_to: String,
+    pub status: String,
+    pub started_at: i64,
+    pub updated_at: i64,
+}
+
+impl Pool {
+    /// Get the OTA history, newest first, optionally only for the client with `mac_addr`.
+    ///
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_ota_history(&self, mac_addr: Option<&str>) -> Result<Vec<WillowOtaHistory>> {
+        tracing::debug!("get_ota_history: {mac_addr:?}");
+
+        let rows =
+            match mac_addr {
+                Some(mac_addr) => query_as::<Any, WillowOtaHistory>(
+                    "SELECT * FROM willow_ota_history WHERE mac_addr = $1 ORDER BY started_at DESC",
+                )
+                .bind(mac_addr)
+                .fetch_all(self.get())
+                .await?,
+                None => {
+                    query_as::<Any, WillowOtaHistory>(
+                        "SELECT * FROM willow_ota_history ORDER BY started_at DESC",
+                    )
+                    .fetch_all(self.get())
+                    .await?
+                }
+            };
+
+        Ok(rows)
+    }
+
+    /// Get the most recent OTA of the client with `mac_addr` that has not succeeded or failed yet.
+    ///
+    /// # Errors
+    /// - if SELECT query fails
+    pub async fn get_pending_ota(&self, mac_addr: &str) -> Result<Option<WillowOtaHistory>> {
+        tracing::debug!("get_pending_ota: {mac_addr}");
+
+        let row = query_as::<Any, WillowOtaHistory>(
+            "SELECT * FROM willow_ota_history WHERE mac_addr = $1
+                    AND status NOT IN ('failed', 'success') ORDER BY started_at DESC LIMIT 1",
+        )
+        .bind(mac_addr)
+        .fetch_optional(self.get())
+        .await?;
+
+        Ok(row)
+    }
+
+    /// # Errors
+    /// - if UPDATE query fails
+    pub async fn update_ota_history_status(
+        &self,
+        id: &str,
+        status: &str,
+        updated_at: i64,
+    ) -> Result<()> {
+        tracing::debug!("update_ota_history_status: {id} -> {status}");
+
+        sqlx::query::<Any>(
+            "UPDATE willow_ota_history SET status = $1, updated_at = $2 WHERE id = $3",
+        )
+        .bind(status)
+        .bind(updated_at)
+        .bind(id)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    /// - if INSERT query fails
+    pub async fn save_ota_history(&self, ota: &WillowOtaHistory) -> Result<()> {
+        tracing::debug!("save_ota_history: {ota:?}");
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_ota_history
+                    (id, mac_addr, hostname, version_from, version_to, status, started_at, updated_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(&ota.id)
+        .bind(&ota.mac_addr)
+        .bind(&ota.hostname)
+        .bind(&ota.version_from)
+        .bind(&ota.version_to)
+        .bind(&ota.status)
+        .bind(ota.started_at)
+        .bind(ota.updated_at)
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+}
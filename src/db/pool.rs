@@ -1,27 +1,351 @@
 use std::env;
 
-use anyhow::Result;
-use sqlx::{AnyPool, any::install_default_drivers};
+use anyhow::{Context, Result, anyhow};
+use sqlx::{
+    Any, AnyPool,
+    any::install_default_drivers,
+    migrate::{MigrateDatabase, MigrateError, Migrator},
+};
+
+use crate::secret::{SecretKey, is_encrypted};
+
+static MIGRATOR: Migrator = sqlx::migrate!();
 
 #[derive(Debug)]
 pub struct Pool {
     pool: AnyPool,
+    secret_key: Option<SecretKey>,
 }
 
 impl Pool {
+    /// Connect to the database and apply pending migrations, creating the SQLite database file if it does not exist.
+    ///
     /// # Errors
-    /// if we fail to create the db pool
+    /// - if we fail to create the SQLite database
+    /// - if we fail to create the db pool
+    /// - if we fail to apply migrations, or the database schema is newer than this version of WAS
     pub async fn create() -> Result<Self> {
         install_default_drivers();
 
         let url = env::var("DATABASE_URL").unwrap_or(String::from("sqlite://was.db"));
-        let pool = AnyPool::connect(&url).await?;
+        if url.starts_with("sqlite:") && !Any::database_exists(&url).await? {
+            tracing::info!("creating database {url}");
+            Any::create_database(&url)
+                .await
+                .context(format!("failed to create database {url}"))?;
+        }
+
+        let pool = Self::new(AnyPool::connect(&url).await?);
+        pool.migrate().await?;
+
+        Ok(pool)
+    }
+
+    /// Apply pending migrations embedded in the binary.
+    ///
+    /// # Errors
+    /// - if the database contains migrations unknown to this version of WAS
+    /// - if applying a migration fails
+    pub async fn migrate(&self) -> Result<()> {
+        match MIGRATOR.run(self.get()).await {
+            Ok(()) => Ok(()),
+            Err(MigrateError::VersionMissing(version)) => Err(anyhow!(
+                "database schema version {version} is newer than this version of WAS supports, refusing to start"
+            )),
+            Err(e) => Err(e).context("failed to apply database migrations"),
+        }
+    }
+
+    #[must_use]
+    pub fn new(pool: AnyPool) -> Self {
+        Self {
+            pool,
+            secret_key: None,
+        }
+    }
 
-        Ok(Self { pool })
+    /// Encrypt secrets saved in the database with `secret_key`, without a key they are stored as plaintext.
+    #[must_use]
+    pub fn with_secret_key(self, secret_key: SecretKey) -> Self {
+        Self {
+            secret_key: Some(secret_key),
+            ..self
+        }
     }
 
     #[must_use]
     pub fn get(&self) -> &AnyPool {
         &self.pool
     }
+
+    pub(super) fn encrypt_secret(&self, value: &str) -> Result<String> {
+        match &self.secret_key {
+            Some(key) => key.encrypt(value),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    pub(super) fn decrypt_secret(&self, value: &str) -> Result<String> {
+        match &self.secret_key {
+            Some(key) => key.decrypt(value),
+            None if is_encrypted(value) => Err(anyhow!("no secret key to decrypt secret")),
+            None => Ok(value.to_string()),
+        }
+    }
+}
+
+/// Create a pool on a migrated in-memory SQLite database for tests. The database lives as long as its only
+/// connection, so the connection is never closed, and tests with paused time do not time out waiting for it.
+#[cfg(test)]
+pub(crate) async fn create_test_pool() -> Pool {
+    install_default_drivers();
+    let pool = sqlx::any::AnyPoolOptions::new()
+        .acquire_timeout(std::time::Duration::from_secs(86_400))
+        .idle_timeout(None)
+        .max_connections(1)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("failed to create database pool");
+    let pool = Pool::new(pool);
+    pool.migrate().await.expect("failed to apply migrations");
+
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use reqwest::Url;
+    use serde_json::json;
+    use sqlx::{Any, AnyPool, Column, Row, migrate::MigrateDatabase};
+
+    use super::{MIGRATOR, Pool, create_test_pool};
+    use crate::{
+        db::ota::WillowOtaHistory, willow::client::WillowClientStatus,
+        willow::messages::WillowNotifyData,
+    };
+
+    /// Save everything twice, so both the insert and the update path of upserts run.
+    async fn check_saves(pool: &Pool) {
+        let mac_addr = "24:0a:c4:00:00:01";
+        for _ in 0..2 {
+            pool.save_willow_client(mac_addr)
+                .await
+                .expect("failed to save client");
+            pool.save_willow_client_label(mac_addr, "kitchen")
+                .await
+                .expect("failed to save client label");
+            pool.save_willow_client_label("24:0a:c4:00:00:02", "office")
+                .await
+                .expect("failed to save client label");
+            pool.save_willow_client_enrollment(
+                "24:0a:c4:00:00:03",
+                WillowClientStatus::Approved,
+                Some("hash"),
+            )
+            .await
+            .expect("failed to save client enrollment");
+            pool.save_willow_config(&json!({"hass_host": "ha.local", "hass_token": "token"}))
+                .await
+                .expect("failed to save config");
+            pool.save_was_config(&json!({"wake_window": "250"}))
+                .await
+                .expect("failed to save WAS config");
+            pool.save_willow_nvs(
+                &json!({"WAS": {"URL": "ws://was"}, "WIFI": {"PSK": "psk", "SSID": "ssid"}}),
+            )
+            .await
+            .expect("failed to save NVS config");
+            pool.save_worker_cache("releases", &json!([]))
+                .await
+                .expect("failed to save worker cache");
+            pool.save_user("admin", "hash", 0)
+                .await
+                .expect("failed to save user");
+        }
+
+        let clients = pool
+            .get_willow_clients()
+            .await
+            .expect("failed to get clients");
+        assert_eq!(clients.len(), 3);
+        assert_eq!(
+            pool.get_willow_client_label(mac_addr)
+                .await
+                .expect("failed to get label"),
+            Some(String::from("kitchen"))
+        );
+        assert_eq!(
+            pool.get_was_config()
+                .await
+                .expect("failed to get WAS config")
+                .wake_window()
+                .as_millis(),
+            250
+        );
+        assert_eq!(
+            pool.get_willow_nvs()
+                .await
+                .expect("failed to get NVS config")
+                .was
+                .url(),
+            "ws://was"
+        );
+        assert!(
+            !pool
+                .save_first_user("other", "hash", 0)
+                .await
+                .expect("failed to save first user")
+        );
+
+        pool.save_notification(
+            "willow-kitchen",
+            &WillowNotifyData {
+                id: 1,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("failed to save notification");
+        pool.save_ota_history(&WillowOtaHistory {
+            id: String::from("ota"),
+            mac_addr: String::from(mac_addr),
+            hostname: None,
+            version_from: String::from("0.3.0"),
+            version_to: String::from("0.3.1"),
+            status: String::from("start"),
+            started_at: 0,
+            updated_at: 0,
+        })
+        .await
+        .expect("failed to save OTA history");
+    }
+
+    /// Revert the migration that rebuilt the tables with surrogate ids and apply it again, keeping the saved data.
+    async fn check_undo(pool: &Pool) {
+        MIGRATOR
+            .undo(pool.get(), 20_261_018_130_000)
+            .await
+            .expect("failed to revert migrations");
+        assert_eq!(
+            columns(pool, "willow_clients").await,
+            ["id", "mac_addr", "label", "status", "token_hash"]
+        );
+        assert_eq!(
+            columns(pool, "willow_config").await,
+            [
+                "id",
+                "config_type",
+                "config_name",
+                "config_namespace",
+                "config_value"
+            ]
+        );
+
+        pool.migrate().await.expect("failed to apply migrations");
+        assert_eq!(
+            columns(pool, "willow_clients").await,
+            ["mac_addr", "label", "status", "token_hash"]
+        );
+        assert_eq!(
+            columns(pool, "willow_config").await,
+            [
+                "config_type",
+                "config_name",
+                "config_namespace",
+                "config_value"
+            ]
+        );
+
+        assert_eq!(
+            pool.get_willow_clients()
+                .await
+                .expect("failed to get clients")
+                .len(),
+            3
+        );
+        let hass_host: (String,) = sqlx::query_as(
+            "SELECT config_value FROM willow_config WHERE config_type = 'config' AND config_name = 'hass_host'",
+        )
+        .fetch_one(pool.get())
+        .await
+        .expect("failed to get config");
+        assert_eq!(hass_host.0, "ha.local");
+        assert_eq!(
+            pool.get_willow_nvs()
+                .await
+                .expect("failed to get NVS config")
+                .was
+                .url(),
+            "ws://was"
+        );
+    }
+
+    /// Get the column names of a table with at least one row.
+    async fn columns(pool: &Pool, table: &str) -> Vec<String> {
+        // not cached, the statement is run again after the schema changed
+        let row = sqlx::query(&format!("SELECT * FROM {table}"))
+            .persistent(false)
+            .fetch_one(pool.get())
+            .await
+            .expect("failed to get row");
+        row.columns().iter().map(|c| c.name().to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_saves() {
+        let pool = create_test_pool().await;
+        check_saves(&pool).await;
+        check_undo(&pool).await;
+    }
+
+    /// Runs against a Postgres server if `WAS_TEST_POSTGRES_URL` is set, e.g. to `postgres://postgres@localhost`,
+    /// in a new database that is dropped afterwards.
+    #[tokio::test]
+    async fn test_saves_postgres() {
+        let Ok(url) = env::var("WAS_TEST_POSTGRES_URL") else {
+            return;
+        };
+        let mut url = Url::parse(&url).expect("invalid WAS_TEST_POSTGRES_URL");
+        url.set_path(&format!("was_test_{}", uuid::Uuid::new_v4().simple()));
+        let url = url.to_string();
+
+        sqlx::any::install_default_drivers();
+        Any::create_database(&url)
+            .await
+            .expect("failed to create database");
+        let pool = Pool::new(
+            AnyPool::connect(&url)
+                .await
+                .expect("failed to connect to database"),
+        );
+        pool.migrate().await.expect("failed to apply migrations");
+
+        check_saves(&pool).await;
+        check_undo(&pool).await;
+
+        pool.get().close().await;
+        Any::force_drop_database(&url)
+            .await
+            .expect("failed to drop database");
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let pool = create_test_pool().await;
+        pool.migrate()
+            .await
+            .expect("failed to apply migrations again");
+
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+                    VALUES (99991231000000, 'future', TRUE, x'00', 0)",
+        )
+        .execute(pool.get())
+        .await
+        .expect("failed to insert future migration");
+        assert!(pool.migrate().await.is_err());
+    }
 }
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use serde_json::Value;
+use sqlx::{Any, FromRow, query_as};
+
+use crate::util::now_ms;
+
+use super::pool::Pool;
+
+#[derive(Debug, FromRow)]
+struct WillowWorkerCacheRow {
+    data: String,
+}
+
+impl Pool {
+    /// Get the last payload `name` successfully fetched from the Willow worker.
+    ///
+    /// # Errors
+    /// - if SELECT query fails
+    /// - if deserializing the saved payload fails
+    pub async fn get_worker_cache(&self, name: &str) -> Result<Option<Value>> {
+        tracing::debug!("get_worker_cache: {name}");
+
+        let row = query_as::<Any, WillowWorkerCacheRow>(
+            "SELECT data FROM willow_worker_cache WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_optional(self.get())
+        .await?;
+
+        row.map(|r| serde_json::from_str(&r.data))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// # Errors
+    /// - if serializing `data` fails
+    /// - if INSERT query fails
+    pub async fn save_worker_cache(&self, name: &str, data: &Value) -> Result<()> {
+        tracing::debug!("save_worker_cache: {name}");
+
+        sqlx::query::<Any>(
+            "INSERT INTO willow_worker_cache (name, data, updated_at) VALUES ($1, $2, $3)
+                    ON CONFLICT(name) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+        )
+        .bind(name)
+        .bind(serde_json::to_string(data)?)
+        .bind(now_ms())
+        .execute(self.get())
+        .await?;
+
+        Ok(())
+    }
+}
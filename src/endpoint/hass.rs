@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use anyhow::{Context, anyhow};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::willow::{config::WillowConfig, messages::WillowCmdEndpointResult};
+
+const HASS_DEFAULT_PORT: u16 = 8123;
+const HASS_RESPONSE_TYPE_ERROR: &str = "error";
+
+#[derive(Serialize)]
+struct HassConversationRequest<'a> {
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct HassConversationResponse {
+    response: HassResponse,
+}
+
+#[derive(Deserialize)]
+struct HassResponse {
+    response_type: String,
+    speech: HassSpeech,
+}
+
+#[derive(Deserialize)]
+struct HassSpeech {
+    plain: Option<HassPlainSpeech>,
+}
+
+#[derive(Deserialize)]
+struct HassPlainSpeech {
+    speech: String,
+}
+
+pub struct HomeAssistantEndpoint {
+    client: Client,
+    token: String,
+    url: Url,
+}
+
+impl HomeAssistantEndpoint {
+    /// # Errors
+    /// - if `base` cannot be joined with the conversation API path
+    /// - if the HTTP client cannot be built
+    pub fn new(base: &Url, token: &str, timeout: Duration) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+        let url = base.join("api/conversation/process")?;
+
+        Ok(Self {
+            client,
+            token: token.to_string(),
+            url,
+        })
+    }
+
+    /// # Errors
+    /// - if `hass_host` or `hass_token` are not set in the config
+    /// - if the Home Assistant URL is invalid
+    /// - if the HTTP client cannot be built
+    pub fn from_config(config: &WillowConfig, timeout: Duration) -> anyhow::Result<Self> {
+        let host = config
+            .hass_host()
+            .as_ref()
+            .ok_or_else(|| anyhow!("hass_host not set in Willow config"))?;
+        let token = config
+            .hass_token()
+            .as_ref()
+            .ok_or_else(|| anyhow!("hass_token not set in Willow config"))?;
+        let port = config.hass_port().unwrap_or(HASS_DEFAULT_PORT);
+        let scheme = if config.hass_tls().unwrap_or(false) {
+            "https"
+        } else {
+            "http"
+        };
+
+        let base = Url::parse(&format!("{scheme}://{host}:{port}/"))
+            .context("invalid Home Assistant URL")?;
+
+        Self::new(&base, token, timeout)
+    }
+
+    /// # Errors
+    /// - if the request to Home Assistant fails or times out
+    /// - if Home Assistant returns a non-success status code
+    /// - if the Home Assistant response cannot be deserialized
+    pub async fn send(&self, text: &str) -> anyhow::Result<WillowCmdEndpointResult> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .bearer_auth(&self.token)
+            .json(&HassConversationRequest { text })
+            .send()
+            .await
+            .context("failed to send command to Home Assistant")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Home Assistant returned {status}"));
+        }
+
+        let response: HassConversationResponse = response
+            .json()
+            .await
+            .context("failed to deserialize Home Assistant response")?;
+
+        Ok(WillowCmdEndpointResult {
+            ok: response.response.response_type != HASS_RESPONSE_TYPE_ERROR,
+            speech: response
+                .response
+                .speech
+                .plain
+                .map(|p| p.speech)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::Url;
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_json, header, method, path},
+    };
+
+    use super::HomeAssistantEndpoint;
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+    const TOKEN: &str = "token";
+
+    fn endpoint(server: &MockServer) -> HomeAssistantEndpoint {
+        let base = Url::parse(&server.uri()).expect("failed to parse mock server URI");
+        HomeAssistantEndpoint::new(&base, TOKEN, TIMEOUT)
+            .expect("failed to create HomeAssistantEndpoint")
+    }
+
+    #[tokio::test]
+    async fn test_send_success() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/conversation/process"))
+            .and(header("Authorization", format!("Bearer {TOKEN}")))
+            .and(body_json(json!({"text": "turn on the lights"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "response": {
+                    "response_type": "action_done",
+                    "speech": {"plain": {"speech": "Turned on the lights"}}
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = endpoint(&server)
+            .send("turn on the lights")
+            .await
+            .expect("failed to send command to Home Assistant");
+
+        assert!(result.ok);
+        assert_eq!(result.speech, "Turned on the lights");
+    }
+
+    #[tokio::test]
+    async fn test_send_auth_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/conversation/process"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let err = endpoint(&server)
+            .send("turn on the lights")
+            .await
+            .expect_err("expected Home Assistant auth failure");
+
+        assert!(err.to_string().contains("401"));
+    }
+
+    #[tokio::test]
+    async fn test_send_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/conversation/process"))
+            .respond_with(ResponseTemplate::new(200).set_delay(TIMEOUT * 4))
+            .mount(&server)
+            .await;
+
+        let result = endpoint(&server).send("turn on the lights").await;
+
+        assert!(result.is_err());
+    }
+}
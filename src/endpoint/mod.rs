@@ -0,0 +1,210 @@
+use anyhow::{Context, anyhow};
+use hass::HomeAssistantEndpoint;
+use mqtt::MqttEndpoint;
+use openhab::OpenHabEndpoint;
+use rest::RestEndpoint;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    state::SharedState,
+    willow::{
+        client::WillowClient,
+        config::WillowCommandEndpoint,
+        messages::{WillowCmdEndpointResult, WillowMsgCmdEndpointResult},
+    },
+};
+
+pub mod hass;
+pub mod mqtt;
+pub mod openhab;
+pub mod rest;
+
+/// Command sent to the MQTT and REST command endpoints.
+#[derive(Serialize)]
+pub struct EndpointRequest<'a> {
+    hostname: Option<&'a str>,
+    mac_addr: Option<&'a str>,
+    text: &'a str,
+}
+
+impl<'a> EndpointRequest<'a> {
+    #[must_use]
+    pub fn new(client: &'a WillowClient, text: &'a str) -> Self {
+        Self {
+            hostname: client.hostname().as_deref(),
+            mac_addr: client.mac_addr().as_deref(),
+            text,
+        }
+    }
+}
+
+/// Send the text of an `endpoint` command to the command endpoint configured in the Willow config, and send the
+/// result back to the client that issued the command.
+///
+/// # Errors
+/// - if we fail to send the result to the client
+pub async fn handle_endpoint_cmd(
+    state: &SharedState,
+    client_id: Uuid,
+    text: &str,
+) -> anyhow::Result<()> {
+    let result = match send_to_endpoint(state, client_id, text).await {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::error!("command endpoint failed: {e:#}");
+            WillowCmdEndpointResult {
+                ok: false,
+                speech: e.to_string(),
+            }
+        }
+    };
+
+    let msg_tx = state.get_msg_tx_by_client_id(client_id).await?;
+    let msg = WillowMsgCmdEndpointResult { result };
+    let msg =
+        serde_json::to_string(&msg).context("failed to serialize WillowMsgCmdEndpointResult")?;
+
+    msg_tx.send(msg.into()).await.context(format!(
+        "failed to send command endpoint result to client {client_id}"
+    ))?;
+
+    Ok(())
+}
+
+async fn send_to_endpoint(
+    state: &SharedState,
+    client_id: Uuid,
+    text: &str,
+) -> anyhow::Result<WillowCmdEndpointResult> {
+    let config = state
+        .db_pool()
+        .get_willow_config()
+        .await
+        .context("failed to get Willow config")?;
+    let was_config = state.db_pool().get_was_config_or_default().await;
+    let timeout = was_config.command_endpoint_timeout();
+
+    match config.command_endpoint() {
+        WillowCommandEndpoint::HomeAssistant => {
+            HomeAssistantEndpoint::from_config(&config, timeout)?
+                .send(text)
+                .await
+        }
+        WillowCommandEndpoint::OpenHab => {
+            OpenHabEndpoint::from_config(&config, timeout)?
+                .send(text)
+                .await
+        }
+        WillowCommandEndpoint::Mqtt => {
+            let client = get_client(state, client_id).await?;
+
+            MqttEndpoint::from_config(&config, timeout)?
+                .with_response_topic(was_config.mqtt_response_topic().clone())
+                .send(state.mqtt_client(), &client, text)
+                .await
+        }
+        WillowCommandEndpoint::Rest => {
+            let client = get_client(state, client_id).await?;
+
+            RestEndpoint::from_config(&config, timeout)?
+                .send(&client, text)
+                .await
+        }
+    }
+}
+
+async fn get_client(state: &SharedState, client_id: Uuid) -> anyhow::Result<WillowClient> {
+    state
+        .clients()
+        .read()
+        .await
+        .get(&client_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("client with id {client_id} not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::{Value, json};
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::handle_endpoint_cmd;
+    use crate::{
+        state::{SharedState, connect_test_client, create_test_state},
+        willow::{client::WillowClient, test_util::read_file},
+    };
+
+    /// Send a command and return the result sent back to the client.
+    async fn send_cmd(state: &SharedState) -> Value {
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_approved(true);
+        let (client_id, mut msg_rx) = connect_test_client(state, client).await;
+
+        handle_endpoint_cmd(state, client_id, "turn on the lights")
+            .await
+            .expect("failed to handle endpoint command");
+        let msg = msg_rx.try_recv().expect("no result sent");
+        let msg: Value = serde_json::from_str(msg.to_text().expect("message is not text"))
+            .expect("invalid message");
+
+        msg["result"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_error_result() {
+        let server = MockServer::start().await;
+        let state = create_test_state().await;
+        let mut config: Value = serde_json::from_str(&read_file("test/willow/config/config.json"))
+            .expect("failed to deserialize config");
+        config["command_endpoint"] = json!("openHAB");
+        config["openhab_url"] = json!(server.uri());
+        state
+            .db_pool()
+            .save_willow_config(&config)
+            .await
+            .expect("failed to save Willow config");
+        state
+            .db_pool()
+            .save_was_config(&json!({"command_endpoint_timeout": "1"}))
+            .await
+            .expect("failed to save WAS config");
+
+        for status in [401, 403] {
+            server.reset().await;
+            Mock::given(method("POST"))
+                .and(path("/rest/voice/interpreters"))
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&server)
+                .await;
+
+            let result = send_cmd(&state).await;
+            assert_eq!(result["ok"], false);
+            assert!(
+                result["speech"]
+                    .as_str()
+                    .is_some_and(|s| s.contains(&status.to_string())),
+                "{result}"
+            );
+        }
+
+        server.reset().await;
+        Mock::given(method("POST"))
+            .and(path("/rest/voice/interpreters"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(4)))
+            .mount(&server)
+            .await;
+
+        let result = send_cmd(&state).await;
+        assert_eq!(result["ok"], false);
+        assert!(result["speech"].as_str().is_some_and(|s| !s.is_empty()));
+    }
+}
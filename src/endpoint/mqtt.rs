@@ -0,0 +1,549 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS, Transport};
+use serde::Serialize;
+use tokio::{
+    sync::{Mutex, oneshot},
+    task::JoinHandle,
+    time::timeout,
+};
+use uuid::Uuid;
+
+use super::EndpointRequest;
+use crate::willow::{
+    client::WillowClient,
+    config::{WillowConfig, WillowMqttAuthType},
+    messages::WillowCmdEndpointResult,
+};
+
+const MQTT_DEFAULT_PORT: u16 = 1883;
+const MQTT_DEFAULT_PORT_TLS: u16 = 8883;
+
+/// Command published to the MQTT broker. With a response topic configured, the reply is expected on
+/// `response_topic`, which is unique to the command.
+#[derive(Serialize)]
+struct MqttRequest<'a> {
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_topic: Option<&'a str>,
+    #[serde(flatten)]
+    request: EndpointRequest<'a>,
+}
+
+/// Broker connection settings from the Willow config.
+#[derive(Clone, Debug, PartialEq)]
+struct MqttSettings {
+    credentials: Option<(String, String)>,
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+impl MqttSettings {
+    fn options(&self) -> MqttOptions {
+        let client_id = format!("was-{}", &Uuid::new_v4().simple().to_string()[..16]);
+        let mut options = MqttOptions::new(client_id, &self.host, self.port);
+
+        if self.tls {
+            options.set_transport(Transport::tls_with_default_config());
+        }
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username, password);
+        }
+
+        options
+    }
+}
+
+pub struct MqttEndpoint {
+    response_topic: Option<String>,
+    settings: MqttSettings,
+    timeout: Duration,
+    topic: String,
+}
+
+impl MqttEndpoint {
+    /// # Errors
+    /// - if `mqtt_host` or `mqtt_topic` are not set in the config
+    /// - if `mqtt_port` is not a valid port number
+    /// - if `mqtt_auth_type` is `userpw` but `mqtt_username` is not set
+    pub fn from_config(config: &WillowConfig, timeout: Duration) -> anyhow::Result<Self> {
+        let host = config
+            .mqtt_host()
+            .as_ref()
+            .ok_or_else(|| anyhow!("mqtt_host not set in Willow config"))?;
+        let topic = config
+            .mqtt_topic()
+            .as_ref()
+            .ok_or_else(|| anyhow!("mqtt_topic not set in Willow config"))?;
+        let tls = config.mqtt_tls().unwrap_or(false);
+        let port = match config.mqtt_port() {
+            Some(port) => port
+                .parse::<u16>()
+                .context(format!("invalid mqtt_port {port}"))?,
+            None if tls => MQTT_DEFAULT_PORT_TLS,
+            None => MQTT_DEFAULT_PORT,
+        };
+
+        let credentials = match config.mqtt_auth_type() {
+            Some(WillowMqttAuthType::UserPw) => {
+                let username = config
+                    .mqtt_username()
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("mqtt_username not set in Willow config"))?;
+                let password = config.mqtt_password().clone().unwrap_or_default();
+                Some((username.clone(), password))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            response_topic: None,
+            settings: MqttSettings {
+                credentials,
+                host: host.clone(),
+                port,
+                tls,
+            },
+            timeout,
+            topic: topic.clone(),
+        })
+    }
+
+    /// Wait for a reply after publishing a command, instead of returning as soon as the broker acknowledged it.
+    /// Replies are expected on `<topic>/<id>`, with the id of the command.
+    #[must_use]
+    pub fn with_response_topic(mut self, topic: Option<String>) -> Self {
+        self.response_topic = topic
+            .map(|t| t.trim_end_matches('/').to_string())
+            .filter(|t| !t.is_empty());
+        self
+    }
+
+    /// # Errors
+    /// - if the command cannot be serialized
+    /// - if the connection to the broker fails
+    /// - if the broker does not acknowledge the command, or no response is received, before the timeout
+    pub async fn send(
+        &self,
+        mqtt: &MqttClient,
+        client: &WillowClient,
+        text: &str,
+    ) -> anyhow::Result<WillowCmdEndpointResult> {
+        let connection = mqtt.connection(self).await;
+
+        let id = Uuid::new_v4().simple().to_string();
+        let response_topic = self
+            .response_topic
+            .as_ref()
+            .map(|topic| format!("{topic}/{id}"));
+        let payload = serde_json::to_vec(&MqttRequest {
+            id: &id,
+            response_topic: response_topic.as_deref(),
+            request: EndpointRequest::new(client, text),
+        })
+        .context("failed to serialize MQTT command")?;
+
+        let reply = match response_topic {
+            Some(_) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                connection
+                    .pending
+                    .replies
+                    .lock()
+                    .await
+                    .insert(id.clone(), reply_tx);
+                Some(reply_rx)
+            }
+            None => None,
+        };
+
+        let result = timeout(self.timeout, async {
+            let ack = connection.publish(&self.topic, payload).await?;
+            match reply {
+                Some(reply) => reply.await.map_err(|_| anyhow!("MQTT connection lost")),
+                None => {
+                    ack.await.map_err(|_| anyhow!("MQTT connection lost"))?;
+                    Ok(WillowCmdEndpointResult {
+                        ok: true,
+                        speech: String::new(),
+                    })
+                }
+            }
+        })
+        .await;
+        connection.pending.replies.lock().await.remove(&id);
+
+        result.map_err(|_| anyhow!("timed out waiting for MQTT broker"))?
+    }
+}
+
+/// Connection to the MQTT broker shared by all commands, replaced when the settings change or the connection fails.
+#[derive(Default)]
+pub struct MqttClient {
+    connection: Mutex<Option<Arc<MqttConnection>>>,
+}
+
+impl std::fmt::Debug for MqttClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MqttClient").finish_non_exhaustive()
+    }
+}
+
+impl MqttClient {
+    async fn connection(&self, endpoint: &MqttEndpoint) -> Arc<MqttConnection> {
+        let mut connection = self.connection.lock().await;
+
+        if let Some(current) = connection.as_ref()
+            && current.settings == endpoint.settings
+            && current.response_topic == endpoint.response_topic
+            && !current.task.is_finished()
+        {
+            return Arc::clone(current);
+        }
+
+        tracing::info!(
+            "connecting to MQTT broker {}:{}",
+            endpoint.settings.host,
+            endpoint.settings.port
+        );
+        let new = Arc::new(MqttConnection::new(
+            endpoint.settings.clone(),
+            endpoint.response_topic.clone(),
+        ));
+        *connection = Some(Arc::clone(&new));
+
+        new
+    }
+}
+
+struct MqttConnection {
+    client: AsyncClient,
+    pending: Arc<MqttPending>,
+    /// Serializes publishing, so acknowledgements can be matched to commands by the order they were published in.
+    publish_lock: Mutex<()>,
+    response_topic: Option<String>,
+    settings: MqttSettings,
+    task: JoinHandle<()>,
+}
+
+/// Commands waiting for the broker or a reply.
+#[derive(Default)]
+struct MqttPending {
+    /// Acknowledgements of published commands by packet id.
+    acks: Mutex<HashMap<u16, oneshot::Sender<()>>>,
+    /// Acknowledgements of commands that were handed to the event loop but have no packet id yet, in order.
+    published: Mutex<VecDeque<oneshot::Sender<()>>>,
+    /// Replies by command id.
+    replies: Mutex<HashMap<String, oneshot::Sender<WillowCmdEndpointResult>>>,
+}
+
+impl MqttConnection {
+    fn new(settings: MqttSettings, response_topic: Option<String>) -> Self {
+        let (client, eventloop) = AsyncClient::new(settings.options(), 10);
+        let pending = Arc::new(MqttPending::default());
+
+        if let Some(topic) = &response_topic
+            && let Err(e) = client.try_subscribe(format!("{topic}/+"), QoS::AtLeastOnce)
+        {
+            tracing::error!("failed to subscribe to MQTT response topic {topic}: {e}");
+        }
+
+        let task = tokio::spawn(run_eventloop(
+            eventloop,
+            Arc::clone(&pending),
+            response_topic.clone(),
+        ));
+
+        Self {
+            client,
+            pending,
+            publish_lock: Mutex::new(()),
+            response_topic,
+            settings,
+            task,
+        }
+    }
+
+    /// Publish `payload` and return a receiver for the acknowledgement by the broker.
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+    ) -> anyhow::Result<oneshot::Receiver<()>> {
+        let _publish = self.publish_lock.lock().await;
+
+        let (ack_tx, ack_rx) = oneshot::channel();
+        self.pending.published.lock().await.push_back(ack_tx);
+        if let Err(e) = self
+            .client
+            .publish(topic, QoS::AtLeastOnce, false, payload)
+            .await
+        {
+            self.pending.published.lock().await.pop_back();
+            return Err(anyhow!("failed to publish MQTT command: {e}"));
+        }
+
+        Ok(ack_rx)
+    }
+}
+
+impl Drop for MqttConnection {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Poll the connection until it fails, handing acknowledgements and replies to the commands waiting for them.
+/// Commands still waiting when the connection fails get an error, and the next command connects again.
+async fn run_eventloop(
+    mut eventloop: EventLoop,
+    pending: Arc<MqttPending>,
+    response_topic: Option<String>,
+) {
+    let prefix = response_topic.map(|topic| format!("{topic}/"));
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Outgoing(Outgoing::Publish(pkid))) => {
+                if let Some(ack_tx) = pending.published.lock().await.pop_front() {
+                    pending.acks.lock().await.insert(pkid, ack_tx);
+                }
+            }
+            Ok(Event::Incoming(Packet::PubAck(ack))) => {
+                if let Some(ack_tx) = pending.acks.lock().await.remove(&ack.pkid) {
+                    let _ = ack_tx.send(());
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let Some(id) = prefix
+                    .as_deref()
+                    .and_then(|prefix| publish.topic.strip_prefix(prefix))
+                else {
+                    continue;
+                };
+                match pending.replies.lock().await.remove(id) {
+                    Some(reply_tx) => {
+                        let _ = reply_tx.send(parse_response(&publish.payload));
+                    }
+                    None => tracing::warn!("ignoring MQTT reply to unknown command {id}"),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("MQTT connection failed: {e}");
+                break;
+            }
+        }
+    }
+
+    pending.acks.lock().await.clear();
+    pending.published.lock().await.clear();
+    pending.replies.lock().await.clear();
+}
+
+/// Accept either a JSON `{"ok": bool, "speech": string}` result or plain text as response.
+fn parse_response(payload: &[u8]) -> WillowCmdEndpointResult {
+    serde_json::from_slice(payload).unwrap_or_else(|_| WillowCmdEndpointResult {
+        ok: true,
+        speech: String::from_utf8_lossy(payload).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use bytes::BytesMut;
+    use rumqttc::{
+        ConnAck, ConnectReturnCode, Packet, PubAck, Publish, QoS, SubAck, SubscribeReasonCode,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{MqttClient, MqttEndpoint, MqttSettings, parse_response};
+    use crate::willow::client::WillowClient;
+
+    const MAX_PACKET_SIZE: usize = 10 * 1024;
+
+    /// Broker that acknowledges everything and answers commands on their response topic, in reverse order once
+    /// two commands arrived.
+    async fn run_broker(listener: TcpListener, connections: Arc<AtomicUsize>) {
+        loop {
+            let (stream, _) = listener.accept().await.expect("failed to accept");
+            connections.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(handle_broker_connection(stream));
+        }
+    }
+
+    async fn handle_broker_connection(mut stream: TcpStream) {
+        let mut read = BytesMut::new();
+        let mut commands = Vec::new();
+
+        loop {
+            let packet = match Packet::read(&mut read, MAX_PACKET_SIZE) {
+                Ok(packet) => packet,
+                Err(_) => {
+                    if stream.read_buf(&mut read).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    continue;
+                }
+            };
+
+            let mut replies = match packet {
+                Packet::Connect(_) => vec![Packet::ConnAck(ConnAck::new(
+                    ConnectReturnCode::Success,
+                    false,
+                ))],
+                Packet::Subscribe(subscribe) => vec![Packet::SubAck(SubAck::new(
+                    subscribe.pkid,
+                    vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)],
+                ))],
+                Packet::Publish(publish) => {
+                    commands.push(
+                        serde_json::from_slice::<serde_json::Value>(&publish.payload)
+                            .expect("invalid command"),
+                    );
+                    vec![Packet::PubAck(PubAck::new(publish.pkid))]
+                }
+                Packet::PingReq => vec![Packet::PingResp],
+                _ => vec![],
+            };
+
+            if commands.len() == 2 {
+                for command in commands.drain(..).rev() {
+                    if let Some(topic) = command["response_topic"].as_str() {
+                        let speech =
+                            format!("done: {}", command["text"].as_str().unwrap_or_default());
+                        replies.push(Packet::Publish(Publish::new(
+                            topic,
+                            QoS::AtMostOnce,
+                            format!(r#"{{"ok": true, "speech": "{speech}"}}"#),
+                        )));
+                    }
+                }
+            }
+
+            let mut write = BytesMut::new();
+            for reply in replies {
+                reply
+                    .write(&mut write, MAX_PACKET_SIZE)
+                    .expect("failed to encode packet");
+            }
+            if stream.write_all(&write).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn start_broker() -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind broker");
+        let port = listener.local_addr().expect("no local address").port();
+        let connections = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(run_broker(listener, Arc::clone(&connections)));
+
+        (port, connections)
+    }
+
+    fn endpoint(port: u16) -> MqttEndpoint {
+        MqttEndpoint {
+            response_topic: None,
+            settings: MqttSettings {
+                credentials: None,
+                host: String::from("127.0.0.1"),
+                port,
+                tls: false,
+            },
+            timeout: Duration::from_secs(5),
+            topic: String::from("willow/cmd"),
+        }
+    }
+
+    fn client(hostname: &str) -> WillowClient {
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_hostname(hostname.to_string());
+        client
+    }
+
+    #[tokio::test]
+    async fn test_send_response() {
+        let (port, connections) = start_broker().await;
+        let mqtt = MqttClient::default();
+        let endpoint = endpoint(port).with_response_topic(Some(String::from("was/reply/")));
+        let kitchen = client("willow-kitchen");
+        let office = client("willow-office");
+
+        // the broker answers the second command first, so each reply must be matched to its command
+        for _ in 0..2 {
+            let (kitchen_result, office_result) = tokio::join!(
+                endpoint.send(&mqtt, &kitchen, "lights on"),
+                endpoint.send(&mqtt, &office, "lights off"),
+            );
+            let kitchen_result = kitchen_result.expect("failed to send command");
+            let office_result = office_result.expect("failed to send command");
+            assert!(kitchen_result.ok);
+            assert_eq!(kitchen_result.speech, "done: lights on");
+            assert!(office_result.ok);
+            assert_eq!(office_result.speech, "done: lights off");
+        }
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_ack() {
+        let (port, connections) = start_broker().await;
+        let mqtt = MqttClient::default();
+        let kitchen = client("willow-kitchen");
+
+        for _ in 0..3 {
+            let result = endpoint(port)
+                .send(&mqtt, &kitchen, "lights on")
+                .await
+                .expect("failed to send command");
+            assert!(result.ok);
+            assert!(result.speech.is_empty());
+        }
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        // a broker that is not running fails the command instead of waiting for the timeout
+        let result = MqttEndpoint {
+            timeout: Duration::from_secs(30),
+            ..endpoint(1)
+        }
+        .send(&mqtt, &kitchen, "lights on")
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let result = parse_response(br#"{"ok": false, "speech": "no such device"}"#);
+        assert!(!result.ok);
+        assert_eq!(result.speech, "no such device");
+
+        let result = parse_response(b"Lights turned on");
+        assert!(result.ok);
+        assert_eq!(result.speech, "Lights turned on");
+    }
+}
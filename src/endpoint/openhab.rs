@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use anyhow::{Context, anyhow};
+use reqwest::{Client, Url, header::CONTENT_TYPE};
+
+use crate::willow::{config::WillowConfig, messages::WillowCmdEndpointResult};
+
+pub struct OpenHabEndpoint {
+    client: Client,
+    token: Option<String>,
+    url: Url,
+}
+
+impl OpenHabEndpoint {
+    /// # Errors
+    /// - if `base` cannot be joined with the voice interpreters API path
+    /// - if the HTTP client cannot be built
+    pub fn new(base: &Url, token: Option<&str>, timeout: Duration) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+
+        let mut base = base.clone();
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let url = base.join("rest/voice/interpreters")?;
+
+        Ok(Self {
+            client,
+            token: token.map(ToString::to_string),
+            url,
+        })
+    }
+
+    /// # Errors
+    /// - if `openhab_url` is not set in the config or is not a valid URL
+    /// - if the HTTP client cannot be built
+    pub fn from_config(config: &WillowConfig, timeout: Duration) -> anyhow::Result<Self> {
+        let url = config
+            .openhab_url()
+            .as_ref()
+            .ok_or_else(|| anyhow!("openhab_url not set in Willow config"))?;
+        let base = Url::parse(url).context("invalid openHAB URL")?;
+
+        Self::new(
+            &base,
+            config.openhab_token().as_deref().filter(|t| !t.is_empty()),
+            timeout,
+        )
+    }
+
+    /// # Errors
+    /// - if the request to openHAB fails or times out
+    /// - if openHAB returns a non-success status code
+    /// - if the openHAB response body cannot be read
+    pub async fn send(&self, text: &str) -> anyhow::Result<WillowCmdEndpointResult> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "text/plain")
+            .body(text.to_string());
+
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .context("failed to send command to openHAB")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("openHAB returned {status}"));
+        }
+
+        let speech = response
+            .text()
+            .await
+            .context("failed to read openHAB response")?;
+
+        Ok(WillowCmdEndpointResult { ok: true, speech })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::Url;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string, header, method, path},
+    };
+
+    use super::OpenHabEndpoint;
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn endpoint(server: &MockServer) -> OpenHabEndpoint {
+        let base = Url::parse(&format!("{}/openhab", server.uri()))
+            .expect("failed to parse mock server URI");
+        OpenHabEndpoint::new(&base, Some("token"), TIMEOUT)
+            .expect("failed to create OpenHabEndpoint")
+    }
+
+    #[tokio::test]
+    async fn test_send_success() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openhab/rest/voice/interpreters"))
+            .and(header("Authorization", "Bearer token"))
+            .and(body_string("turn on the lights"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Lights turned on"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = endpoint(&server)
+            .send("turn on the lights")
+            .await
+            .expect("failed to send command to openHAB");
+
+        assert!(result.ok);
+        assert_eq!(result.speech, "Lights turned on");
+    }
+
+    #[tokio::test]
+    async fn test_send_auth_failure() {
+        for status in [401, 403] {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .and(path("/openhab/rest/voice/interpreters"))
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&server)
+                .await;
+
+            let err = endpoint(&server)
+                .send("turn on the lights")
+                .await
+                .expect_err("expected openHAB auth failure");
+
+            assert!(err.to_string().contains(&status.to_string()), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openhab/rest/voice/interpreters"))
+            .respond_with(ResponseTemplate::new(200).set_delay(TIMEOUT * 4))
+            .mount(&server)
+            .await;
+
+        let result = endpoint(&server).send("turn on the lights").await;
+
+        assert!(result.is_err());
+    }
+}
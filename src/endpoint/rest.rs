@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use anyhow::{Context, anyhow};
+use reqwest::{Client, Url, header::AUTHORIZATION};
+
+use super::EndpointRequest;
+use crate::willow::{
+    client::WillowClient,
+    config::{WillowConfig, WillowRestAuthType},
+    messages::WillowCmdEndpointResult,
+};
+
+#[derive(Debug)]
+pub enum RestAuth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Header(String),
+    None,
+}
+
+pub struct RestEndpoint {
+    auth: RestAuth,
+    client: Client,
+    url: Url,
+}
+
+impl RestEndpoint {
+    /// # Errors
+    /// - if the HTTP client cannot be built
+    pub fn new(url: Url, auth: RestAuth, timeout: Duration) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+
+        Ok(Self { auth, client, url })
+    }
+
+    /// # Errors
+    /// - if `rest_url` is not set in the config or is not a valid URL
+    /// - if the configured auth type is missing its credentials
+    /// - if the HTTP client cannot be built
+    pub fn from_config(config: &WillowConfig, timeout: Duration) -> anyhow::Result<Self> {
+        let url = config
+            .rest_url()
+            .as_ref()
+            .ok_or_else(|| anyhow!("rest_url not set in Willow config"))?;
+        let url = Url::parse(url).context("invalid REST URL")?;
+
+        let auth = match config.rest_auth_type() {
+            Some(WillowRestAuthType::Basic) => RestAuth::Basic {
+                username: config
+                    .rest_auth_user()
+                    .clone()
+                    .ok_or_else(|| anyhow!("rest_auth_user not set in Willow config"))?,
+                password: config.rest_auth_pass().clone(),
+            },
+            Some(WillowRestAuthType::Header) => RestAuth::Header(
+                config
+                    .rest_auth_header()
+                    .clone()
+                    .ok_or_else(|| anyhow!("rest_auth_header not set in Willow config"))?,
+            ),
+            Some(WillowRestAuthType::NoneType) | None => RestAuth::None,
+        };
+
+        Self::new(url, auth, timeout)
+    }
+
+    /// # Errors
+    /// - if the request to the REST endpoint fails or times out
+    /// - if the REST endpoint returns a non-success status code
+    /// - if the response body cannot be read
+    pub async fn send(
+        &self,
+        client: &WillowClient,
+        text: &str,
+    ) -> anyhow::Result<WillowCmdEndpointResult> {
+        let request = self
+            .client
+            .post(self.url.clone())
+            .json(&EndpointRequest::new(client, text));
+        let request = match &self.auth {
+            RestAuth::Basic { username, password } => {
+                request.basic_auth(username, password.as_ref())
+            }
+            RestAuth::Header(value) => request.header(AUTHORIZATION, value),
+            RestAuth::None => request,
+        };
+
+        let response = request
+            .send()
+            .await
+            .context("failed to send command to REST endpoint")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("REST endpoint returned {status}"));
+        }
+
+        let speech = response
+            .text()
+            .await
+            .context("failed to read REST endpoint response")?;
+
+        Ok(WillowCmdEndpointResult { ok: true, speech })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::Url;
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_json, header, method},
+    };
+
+    use super::{RestAuth, RestEndpoint};
+    use crate::willow::client::WillowClient;
+
+    async fn send(server: &MockServer, auth: RestAuth) -> anyhow::Result<String> {
+        let url = Url::parse(&server.uri()).expect("failed to parse mock server URI");
+        let result = RestEndpoint::new(url, auth, Duration::from_secs(1))
+            .expect("failed to create RestEndpoint")
+            .send(&WillowClient::default(), "turn on the lights")
+            .await?;
+
+        Ok(result.speech)
+    }
+
+    #[tokio::test]
+    async fn test_send_basic_auth() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("Authorization", "Basic dXNlcjpwYXNz"))
+            .and(body_json(json!({
+                "hostname": null,
+                "mac_addr": null,
+                "text": "turn on the lights"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let auth = RestAuth::Basic {
+            username: String::from("user"),
+            password: Some(String::from("pass")),
+        };
+        let speech = send(&server, auth)
+            .await
+            .expect("failed to send command to REST endpoint");
+
+        assert_eq!(speech, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_send_header_auth() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let speech = send(&server, RestAuth::Header(String::from("Bearer token")))
+            .await
+            .expect("failed to send command to REST endpoint");
+        assert_eq!(speech, "ok");
+
+        let result = send(&server, RestAuth::None).await;
+        assert!(result.is_err());
+    }
+}
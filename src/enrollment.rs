@@ -0,0 +1,204 @@
+use anyhow::{Context, anyhow};
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_token, hash_token},
+    state::SharedState,
+    util::now_ms,
+    willow::{client::WillowClientStatus, messages::WillowMsgToken},
+};
+
+/// Decide whether a client that said hello may get config and send messages other than hello and goodbye, and return
+/// whether it was approved.
+///
+/// With device enrollment enabled, a client is only approved if its MAC address was approved and it presented the
+/// device token it got on approval. Unknown clients are held as pending until they are approved via the API.
+///
+/// Clients are marked authenticated if they presented an API token or their approved device token, see
+/// [`require_authenticated`].
+///
+/// # Errors
+/// - if we fail to get the client or the API token from the database
+pub async fn enroll_client(
+    state: &SharedState,
+    client_id: Uuid,
+    mac_addr: &str,
+) -> anyhow::Result<bool> {
+    let token_hash = state
+        .clients()
+        .read()
+        .await
+        .get(&client_id)
+        .and_then(|c| c.token_hash().clone());
+
+    let device_token = match (
+        &token_hash,
+        state
+            .db_pool()
+            .get_willow_client_enrollment(mac_addr)
+            .await?,
+    ) {
+        (Some(token_hash), Some((WillowClientStatus::Approved, Some(approved_hash)))) => {
+            *token_hash == approved_hash
+        }
+        _ => false,
+    };
+    let api_token = match &token_hash {
+        Some(token_hash) if !device_token => state
+            .db_pool()
+            .get_api_token_user(token_hash, now_ms())
+            .await?
+            .is_some(),
+        _ => false,
+    };
+    if token_hash.is_some() && !device_token && !api_token {
+        tracing::warn!("client {mac_addr} did not present a valid token");
+    }
+
+    let approved = if state
+        .db_pool()
+        .get_was_config_or_default()
+        .await
+        .device_enrollment()
+    {
+        if !device_token {
+            tracing::info!("client {mac_addr} is pending enrollment approval");
+        }
+        device_token
+    } else {
+        true
+    };
+
+    if let Some(client) = state.clients().write().await.get_mut(&client_id) {
+        client.set_approved(approved);
+        client.set_authenticated(device_token || api_token);
+    }
+
+    Ok(approved)
+}
+
+/// Approve a client and return its new device token, which is also sent to the client if it is connected.
+///
+/// # Errors
+/// - if generating the token fails
+/// - if we fail to save the enrollment in the database
+pub async fn approve_client(state: &SharedState, mac_addr: &str) -> anyhow::Result<String> {
+    let token = generate_token()?;
+    let token_hash = hash_token(&token);
+
+    state
+        .db_pool()
+        .save_willow_client_enrollment(mac_addr, WillowClientStatus::Approved, Some(&token_hash))
+        .await
+        .context(format!("failed to approve client {mac_addr}"))?;
+
+    let msg = serde_json::to_string(&WillowMsgToken {
+        token: token.clone(),
+    })?;
+    for client_id in set_clients_enrollment(state, mac_addr, Some(token_hash), true).await {
+        if let Err(e) = send_token(state, client_id, &msg).await {
+            tracing::error!("{e:#}");
+        }
+    }
+
+    Ok(token)
+}
+
+/// Revoke the approval and device token of a client. Connected clients can no longer get config, and lose access if
+/// device enrollment is enabled.
+///
+/// # Errors
+/// - if we fail to save the enrollment in the database
+pub async fn revoke_client(state: &SharedState, mac_addr: &str) -> anyhow::Result<()> {
+    state
+        .db_pool()
+        .save_willow_client_enrollment(mac_addr, WillowClientStatus::Pending, None)
+        .await
+        .context(format!("failed to revoke client {mac_addr}"))?;
+
+    let approved = !state
+        .db_pool()
+        .get_was_config_or_default()
+        .await
+        .device_enrollment();
+    set_clients_enrollment(state, mac_addr, None, approved).await;
+
+    Ok(())
+}
+
+/// # Errors
+/// - if the client is not approved
+pub async fn require_approved(state: &SharedState, client_id: Uuid) -> anyhow::Result<()> {
+    let approved = state
+        .clients()
+        .read()
+        .await
+        .get(&client_id)
+        .is_some_and(|c| c.approved());
+
+    if approved {
+        Ok(())
+    } else {
+        Err(anyhow!("client {client_id} is not approved"))
+    }
+}
+
+/// Config contains secrets, so with device enrollment enabled it is only sent to clients that presented an API token or
+/// their approved device token. Stock firmware does not send a token, so without device enrollment any client may get
+/// config.
+///
+/// # Errors
+/// - if device enrollment is enabled and the client did not present an API token or its approved device token
+pub async fn require_authenticated(state: &SharedState, client_id: Uuid) -> anyhow::Result<()> {
+    if !state
+        .db_pool()
+        .get_was_config_or_default()
+        .await
+        .device_enrollment()
+    {
+        return Ok(());
+    }
+
+    let authenticated = state
+        .clients()
+        .read()
+        .await
+        .get(&client_id)
+        .is_some_and(|c| c.authenticated());
+
+    if authenticated {
+        Ok(())
+    } else {
+        Err(anyhow!("client {client_id} is not authenticated"))
+    }
+}
+
+/// Update connected clients with `mac_addr`, authenticating them with the device token if `token_hash` is set, and
+/// return their ids.
+async fn set_clients_enrollment(
+    state: &SharedState,
+    mac_addr: &str,
+    token_hash: Option<String>,
+    approved: bool,
+) -> Vec<Uuid> {
+    let mut ids = Vec::new();
+    for (id, client) in state.clients().write().await.iter_mut() {
+        if client.mac_addr().as_deref() == Some(mac_addr) {
+            client.set_approved(approved);
+            client.set_authenticated(token_hash.is_some());
+            client.set_token_hash(token_hash.clone());
+            ids.push(*id);
+        }
+    }
+
+    ids
+}
+
+async fn send_token(state: &SharedState, client_id: Uuid, msg: &str) -> anyhow::Result<()> {
+    state
+        .get_msg_tx_by_client_id(client_id)
+        .await?
+        .send(msg.into())
+        .await
+        .context(format!("failed to send device token to client {client_id}"))
+}
@@ -13,6 +13,10 @@ pub enum WasApiError {
     BadRequestError(String),
     #[error("internal server error: {0}")]
     InternalServerError(String),
+    #[error("not found: {0}")]
+    NotFoundError(String),
+    #[error("unauthorized: {0}")]
+    UnauthorizedError(String),
 }
 
 #[derive(Debug, Serialize)]
@@ -33,6 +37,8 @@ impl IntoResponse for WasApiError {
         let (status_code, msg) = match self {
             WasApiError::BadRequestError(msg) => (StatusCode::BAD_REQUEST, msg),
             WasApiError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            WasApiError::NotFoundError(msg) => (StatusCode::NOT_FOUND, msg),
+            WasApiError::UnauthorizedError(msg) => (StatusCode::UNAUTHORIZED, msg),
         };
 
         (status_code, Json(WasApiErrorResponse { msg })).into_response()
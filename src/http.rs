@@ -1,5 +1,6 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
 
+use anyhow::Context;
 use axum::{
     Json, Router,
     extract::Request,
@@ -7,53 +8,135 @@ use axum::{
     response::{IntoResponse, Redirect},
     routing::get,
 };
-use reqwest::{Method, header::CONTENT_TYPE};
-use tokio::net::TcpListener;
-use tower_http::{cors::CorsLayer, services::ServeDir};
+use axum_server::tls_rustls::RustlsConfig;
+use reqwest::{Method, header::HeaderName};
+use tokio::task::JoinSet;
+use tower_http::{
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
+    services::ServeDir,
+};
 
 use crate::{
     api::api_routes,
+    cli::{CorsConfig, ServerConfig},
+    notify::notify_scheduler,
     state::SharedState,
     websocket::{get_ws, send_ping},
+    willow::worker::worker_refresher,
 };
 
+/// Serve the API and websocket on all addresses in `config`, with TLS if configured.
+///
 /// # Errors
-/// - if `TcpListener` cannot bind
+/// - if the CORS config is invalid
+/// - if the TLS certificate or key cannot be loaded
+/// - if binding to any of the addresses fails
 /// - if axum server cannot be started
-pub async fn serve(state: SharedState) -> anyhow::Result<()> {
-    let allow_origin = HeaderValue::from_str("http://localhost:3000")?;
-
+pub async fn serve(state: SharedState, config: &ServerConfig) -> anyhow::Result<()> {
     let router = Router::new()
         .fallback(fallback)
         .nest("/api", api_routes(&state))
         .nest_service("/admin", ServeDir::new("static/admin"))
         .route("/", get(|| async { Redirect::temporary("/admin") }))
         .route("/ws", get(get_ws).with_state(Arc::clone(&state)))
-        .layer(
-            CorsLayer::new()
-                .allow_headers([CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST])
-                .allow_origin(allow_origin),
-        );
+        .layer(cors_layer(config.cors())?);
 
     tracing::debug!("{router:#?}");
 
-    let port = 8502;
-    let address = format!("[::]:{port}");
-
-    let listener = TcpListener::bind(address).await?;
+    let tls = match config.tls() {
+        Some(tls) => Some(
+            RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+                .await
+                .context("failed to load TLS certificate and key")?,
+        ),
+        None => None,
+    };
 
     tokio::spawn(send_ping(Arc::clone(&state)));
+    tokio::spawn(notify_scheduler(Arc::clone(&state)));
+    tokio::spawn(worker_refresher(Arc::clone(&state)));
+
+    let mut servers = JoinSet::new();
+    for address in config.listen() {
+        let service = router
+            .clone()
+            .into_make_service_with_connect_info::<SocketAddr>();
+
+        match &tls {
+            Some(tls) => {
+                tracing::info!("listening on https://{address}");
+                servers.spawn(axum_server::bind_rustls(*address, tls.clone()).serve(service));
+            }
+            None => {
+                tracing::info!("listening on http://{address}");
+                servers.spawn(axum_server::bind(*address).serve(service));
+            }
+        }
+    }
 
-    axum::serve(
-        listener,
-        router.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await?;
+    while let Some(result) = servers.join_next().await {
+        result?.context("server failed")?;
+    }
 
     Ok(())
 }
 
+/// # Errors
+/// - if any of the allowed origins, methods or headers is invalid
+fn cors_layer(config: &CorsConfig) -> anyhow::Result<CorsLayer> {
+    if config.permissive {
+        tracing::warn!("CORS requests from any origin allowed");
+        return Ok(CorsLayer::very_permissive());
+    }
+
+    let is_any = |values: &[String]| values.iter().any(|v| v == "*");
+
+    let headers = if is_any(&config.headers) {
+        AllowHeaders::any()
+    } else {
+        config
+            .headers
+            .iter()
+            .map(|h| HeaderName::from_str(h).context(format!("invalid CORS header {h}")))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into()
+    };
+
+    let methods = if is_any(&config.methods) {
+        AllowMethods::any()
+    } else {
+        config
+            .methods
+            .iter()
+            .map(|m| {
+                Method::from_str(&m.to_uppercase()).context(format!("invalid CORS method {m}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into()
+    };
+
+    let origins = if is_any(&config.origins) {
+        AllowOrigin::any()
+    } else {
+        config
+            .origins
+            .iter()
+            .map(|o| HeaderValue::from_str(o).context(format!("invalid CORS origin {o}")))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into()
+    };
+
+    // credentials, i.e. the session cookie, cannot be allowed for wildcards
+    let credentials =
+        !(is_any(&config.headers) || is_any(&config.methods) || is_any(&config.origins));
+
+    Ok(CorsLayer::new()
+        .allow_credentials(credentials)
+        .allow_headers(headers)
+        .allow_methods(methods)
+        .allow_origin(origins))
+}
+
 async fn fallback(request: Request) -> impl IntoResponse {
     let uri = request.uri();
 
@@ -61,3 +144,23 @@ async fn fallback(request: Request) -> impl IntoResponse {
 
     (StatusCode::NOT_FOUND, Json(format!("invalid URI {uri}")))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::cors_layer;
+    use crate::cli::CorsConfig;
+
+    #[test]
+    fn test_cors_layer() {
+        let config = |methods: &[&str]| CorsConfig {
+            headers: vec![String::from("content-type"), String::from("authorization")],
+            methods: methods.iter().map(ToString::to_string).collect(),
+            origins: vec![String::from("https://was.example.com")],
+            permissive: false,
+        };
+
+        assert!(cors_layer(&config(&["get", "POST", "PUT", "DELETE", "PATCH"])).is_ok());
+        assert!(cors_layer(&config(&["*"])).is_ok());
+        assert!(cors_layer(&config(&["GET POST"])).is_err());
+    }
+}
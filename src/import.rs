@@ -0,0 +1,528 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, anyhow};
+use eui48::MacAddress;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::{
+    AnyConnection, FromRow, Sqlite, SqlitePool, query_as,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use crate::{cli::ImportArgs, db::pool::Pool, willow::messages::WillowNotifyData};
+
+/// Fields of `WillowConfig`, settings with other names are reported as unknown.
+const CONFIG_FIELDS: &[&str] = &[
+    "aec",
+    "audio_codec",
+    "audio_response_type",
+    "bss",
+    "command_endpoint",
+    "display_timeout",
+    "hass_host",
+    "hass_port",
+    "hass_tls",
+    "hass_token",
+    "lcd_brightness",
+    "mic_gain",
+    "mqtt_auth_type",
+    "mqtt_host",
+    "mqtt_password",
+    "mqtt_port",
+    "mqtt_tls",
+    "mqtt_topic",
+    "mqtt_username",
+    "multiwake",
+    "ntp_config",
+    "ntp_host",
+    "openhab_token",
+    "openhab_url",
+    "record_buffer",
+    "rest_auth_header",
+    "rest_auth_pass",
+    "rest_auth_type",
+    "rest_auth_user",
+    "rest_url",
+    "show_prereleases",
+    "speaker_volume",
+    "speech_rec_mode",
+    "stream_timeout",
+    "timezone",
+    "timezone_name",
+    "vad_mode",
+    "vad_timeout",
+    "wake_confirmation",
+    "wake_mode",
+    "wake_word",
+    "was_mode",
+    "wis_tts_url",
+    "wis_tts_url_v2",
+    "wis_url",
+];
+
+/// Tables the Python WAS may have stored pending notifications in.
+const NOTIFICATION_TABLES: &[&str] = &["willow_notifications", "notifications"];
+
+#[derive(Debug, FromRow)]
+struct PythonClientRow {
+    mac_addr: String,
+    label: Option<String>,
+}
+
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, FromRow)]
+struct PythonConfigRow {
+    config_type: String,
+    config_namespace: Option<String>,
+    config_name: String,
+    config_value: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct PythonNotificationRow {
+    hostname: String,
+    data: String,
+}
+
+#[derive(Debug, FromRow)]
+struct TableRow {
+    name: String,
+}
+
+/// What was imported, and what could not be.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub clients: usize,
+    pub config: usize,
+    pub notifications: usize,
+    pub nvs: usize,
+    pub was: usize,
+    /// Settings that don't map to `WillowConfig` fields, prefixed with their source.
+    pub unknown_keys: Vec<String>,
+    /// Records that could not be imported, with the reason.
+    pub skipped: Vec<String>,
+}
+
+/// Import the Python WAS database and legacy config files given in `args`.
+///
+/// Config from the database takes precedence over the legacy files, as the Python WAS migrated the files to the
+/// database on startup. Everything is saved in one transaction, so a failed import saves nothing and can be rerun.
+///
+/// # Errors
+/// - if neither a database nor a storage directory is given
+/// - if the database or config files cannot be read
+/// - if we fail to save the imported data
+pub async fn import(pool: &Pool, args: &ImportArgs) -> anyhow::Result<ImportReport> {
+    if args.database.is_none() && args.storage.is_none() {
+        return Err(anyhow!("either --database or --storage is required"));
+    }
+
+    let mut report = ImportReport::default();
+    let mut tx = pool.get().begin().await?;
+
+    if let Some(storage) = &args.storage {
+        import_storage(pool, &mut tx, storage, &mut report).await?;
+    }
+    if let Some(database) = &args.database {
+        import_database(pool, &mut tx, database, &mut report).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+async fn import_storage(
+    pool: &Pool,
+    tx: &mut AnyConnection,
+    dir: &Path,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    if let Some(config) = read_json_file(&dir.join("user_config.json"))? {
+        let Value::Object(config) = config else {
+            return Err(anyhow!("user_config.json does not contain an object"));
+        };
+        let config = known_config(config, "user_config.json", report);
+        report.config += config.len();
+        pool.save_willow_config_tx(tx, &Value::Object(config))
+            .await?;
+    }
+
+    if let Some(nvs) = read_json_file(&dir.join("user_nvs.json"))? {
+        report.nvs += nvs
+            .as_object()
+            .map(|ns| {
+                ns.values()
+                    .filter_map(Value::as_object)
+                    .map(Map::len)
+                    .sum::<usize>()
+            })
+            .unwrap_or_default();
+        pool.save_willow_nvs_tx(tx, &nvs).await?;
+    }
+
+    Ok(())
+}
+
+async fn import_database(
+    pool: &Pool,
+    tx: &mut AnyConnection,
+    path: &Path,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    if !path.is_file() {
+        return Err(anyhow!("database {} not found", path.display()));
+    }
+
+    let source = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(SqliteConnectOptions::new().filename(path).read_only(true))
+        .await
+        .context(format!("failed to open database {}", path.display()))?;
+
+    let tables =
+        query_as::<Sqlite, TableRow>("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .fetch_all(&source)
+            .await?;
+    let has_table = |name: &str| tables.iter().any(|t| t.name == name);
+
+    if has_table("willow_config") {
+        import_config(pool, tx, &source, report).await?;
+    } else {
+        report
+            .skipped
+            .push(String::from("willow_config: table not found"));
+    }
+
+    if has_table("willow_clients") {
+        import_clients(pool, tx, &source, report).await?;
+    } else {
+        report
+            .skipped
+            .push(String::from("willow_clients: table not found"));
+    }
+
+    for table in NOTIFICATION_TABLES.iter().filter(|t| has_table(t)) {
+        import_notifications(pool, tx, &source, table, report).await?;
+    }
+
+    source.close().await;
+
+    Ok(())
+}
+
+async fn import_config(
+    pool: &Pool,
+    tx: &mut AnyConnection,
+    source: &SqlitePool,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    let rows = query_as::<Sqlite, PythonConfigRow>(
+        "SELECT config_type, config_namespace, config_name, config_value FROM willow_config",
+    )
+    .fetch_all(source)
+    .await?;
+
+    let mut config = Map::new();
+    let mut nvs: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    let mut was = Map::new();
+
+    for row in rows {
+        let value = row.config_value.map_or(Value::Null, Value::String);
+        match row.config_type.to_lowercase().as_str() {
+            "config" => {
+                config.insert(row.config_name, value);
+            }
+            "nvs" => match row.config_namespace {
+                Some(namespace) => {
+                    nvs.entry(namespace.to_uppercase())
+                        .or_default()
+                        .insert(row.config_name, value);
+                }
+                None => report.skipped.push(format!(
+                    "willow_config: NVS setting {} without namespace",
+                    row.config_name
+                )),
+            },
+            "was" => {
+                was.insert(row.config_name, value);
+            }
+            other => report.skipped.push(format!(
+                "willow_config: setting {} with unknown type {other}",
+                row.config_name
+            )),
+        }
+    }
+
+    if !config.is_empty() {
+        let config = known_config(config, "willow_config", report);
+        report.config += config.len();
+        pool.save_willow_config_tx(tx, &Value::Object(config))
+            .await?;
+    }
+
+    if !nvs.is_empty() {
+        report.nvs += nvs.values().map(Map::len).sum::<usize>();
+        pool.save_willow_nvs_tx(tx, &serde_json::to_value(nvs)?)
+            .await?;
+    }
+
+    if !was.is_empty() {
+        let count = was.len();
+        match pool.save_was_config_tx(tx, &Value::Object(was)).await {
+            Ok(()) => report.was += count,
+            Err(e) => report
+                .skipped
+                .push(format!("willow_config: WAS settings: {e}")),
+        }
+    }
+
+    Ok(())
+}
+
+async fn import_clients(
+    pool: &Pool,
+    tx: &mut AnyConnection,
+    source: &SqlitePool,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    let rows = query_as::<Sqlite, PythonClientRow>("SELECT mac_addr, label FROM willow_clients")
+        .fetch_all(source)
+        .await?;
+
+    for row in rows {
+        let mac_addr = match MacAddress::parse_str(&row.mac_addr) {
+            Ok(mac_addr) => mac_addr.to_hex_string(),
+            Err(e) => {
+                report.skipped.push(format!(
+                    "willow_clients: invalid MAC address {}: {e}",
+                    row.mac_addr
+                ));
+                continue;
+            }
+        };
+
+        pool.save_willow_client_label_tx(tx, &mac_addr, row.label.unwrap_or_default().trim())
+            .await?;
+        report.clients += 1;
+    }
+
+    Ok(())
+}
+
+async fn import_notifications(
+    pool: &Pool,
+    tx: &mut AnyConnection,
+    source: &SqlitePool,
+    table: &str,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    let rows = match query_as::<Sqlite, PythonNotificationRow>(&format!(
+        "SELECT hostname, data FROM {table}"
+    ))
+    .fetch_all(source)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            report.skipped.push(format!("{table}: {e}"));
+            return Ok(());
+        }
+    };
+
+    for row in rows {
+        match serde_json::from_str::<WillowNotifyData>(&row.data) {
+            Ok(data) => {
+                pool.save_notification_tx(tx, &row.hostname, &data).await?;
+                report.notifications += 1;
+            }
+            Err(e) => report.skipped.push(format!(
+                "{table}: invalid notification for {}: {e}",
+                row.hostname
+            )),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_json_file(path: &Path) -> anyhow::Result<Option<Value>> {
+    if !path.is_file() {
+        tracing::info!("{} not found, skipping", path.display());
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path).context(format!("failed to read {}", path.display()))?;
+
+    Ok(Some(
+        serde_json::from_str(&content).context(format!("invalid JSON in {}", path.display()))?,
+    ))
+}
+
+/// Keep only settings that map to `WillowConfig` fields, and report the others as unknown.
+fn known_config(
+    config: Map<String, Value>,
+    source: &str,
+    report: &mut ImportReport,
+) -> Map<String, Value> {
+    config
+        .into_iter()
+        .filter(|(key, _)| {
+            let known = CONFIG_FIELDS.contains(&key.as_str());
+            if !known {
+                report.unknown_keys.push(format!("{source}: {key}"));
+            }
+            known
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fs, path::PathBuf};
+
+    use serde_json::{Map, Value};
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    use super::{CONFIG_FIELDS, import};
+    use crate::{
+        cli::ImportArgs,
+        db::pool::create_test_pool,
+        willow::{config::WillowConfig, test_util::read_file},
+    };
+
+    #[test]
+    fn test_config_fields() {
+        let fields: BTreeSet<&str> = CONFIG_FIELDS.iter().copied().collect();
+        assert_eq!(fields.len(), CONFIG_FIELDS.len());
+
+        // every field is set in the test data, so fields missing from the list or the struct are caught either way
+        let test_data: Map<String, Value> =
+            serde_json::from_str(&read_file("test/willow/config/config.json"))
+                .expect("failed to deserialize config test data");
+        let config: WillowConfig = serde_json::from_value(Value::Object(test_data.clone()))
+            .expect("failed to deserialize config");
+        let Value::Object(config) =
+            serde_json::to_value(config).expect("failed to serialize config")
+        else {
+            panic!("config did not serialize to an object");
+        };
+
+        assert_eq!(
+            test_data
+                .keys()
+                .map(String::as_str)
+                .collect::<BTreeSet<_>>(),
+            fields
+        );
+        assert_eq!(
+            config.keys().map(String::as_str).collect::<BTreeSet<_>>(),
+            fields
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        // characters that have a meaning in database URLs
+        let dir = std::env::temp_dir().join(format!("was-import-{}?#%", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).expect("failed to create temp dir");
+
+        let database = dir.join("was.db");
+        let source = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .filename(&database)
+                    .create_if_missing(true),
+            )
+            .await
+            .expect("failed to create source database");
+        sqlx::raw_sql(
+            &fs::read_to_string("test/import/python_was.sql").expect("failed to read test data"),
+        )
+        .execute(&source)
+        .await
+        .expect("failed to create source database");
+        source.close().await;
+
+        fs::copy("test/import/user_config.json", dir.join("user_config.json"))
+            .expect("failed to copy test data");
+
+        let pool = create_test_pool().await;
+
+        // the storage directory is imported first, and is not saved when the database fails
+        let failed = import(
+            &pool,
+            &ImportArgs {
+                database: Some(dir.join("missing.db")),
+                storage: Some(dir.clone()),
+            },
+        )
+        .await;
+        assert!(failed.is_err());
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM willow_config")
+            .fetch_one(pool.get())
+            .await
+            .expect("failed to count config");
+        assert_eq!(count, 0);
+
+        let args = ImportArgs {
+            database: Some(database),
+            storage: Some(dir.clone()),
+        };
+        let report = import(&pool, &args).await.expect("failed to import");
+        // importing again changes nothing
+        import(&pool, &args).await.expect("failed to import again");
+        fs::remove_dir_all(&dir).expect("failed to remove temp dir");
+
+        assert_eq!(report.clients, 2);
+        assert_eq!(report.nvs, 3);
+        assert_eq!(report.notifications, 1);
+        assert_eq!(
+            pool.get_notifications()
+                .await
+                .expect("failed to get notifications")
+                .len(),
+            1
+        );
+        assert_eq!(
+            report.unknown_keys,
+            vec!["user_config.json: legacy_key", "willow_config: old_setting"]
+        );
+        assert_eq!(report.skipped.len(), 1);
+
+        let config = serde_json::to_value(
+            pool.get_willow_config()
+                .await
+                .expect("failed to get imported config"),
+        )
+        .expect("failed to serialize config");
+        assert_eq!(config["hass_host"], "ha.example.com");
+        assert_eq!(config["wis_url"], "https://infer.tovera.io/api/willow");
+
+        assert_eq!(
+            pool.get_willow_client_label("24:0a:c4:00:00:01")
+                .await
+                .expect("failed to get label"),
+            Some(String::from("kitchen"))
+        );
+        assert_eq!(
+            pool.get_willow_nvs()
+                .await
+                .expect("failed to get NVS config")
+                .was
+                .url(),
+            "ws://was.example.com:8502/ws"
+        );
+
+        let missing = import(
+            &pool,
+            &ImportArgs {
+                database: Some(PathBuf::from("/nonexistent/was.db")),
+                storage: None,
+            },
+        )
+        .await;
+        assert!(missing.is_err());
+    }
+}
@@ -1,8 +1,20 @@
 pub mod api;
+pub mod auth;
+pub mod cli;
+pub mod config;
 pub mod db;
+pub mod endpoint;
+pub mod enrollment;
 pub mod error;
 pub mod http;
+pub mod import;
+pub mod notify;
+pub mod ota;
+pub mod rollout;
+pub mod secret;
 pub mod state;
 pub mod trace;
+pub mod util;
+pub mod wake;
 pub mod websocket;
 pub mod willow;
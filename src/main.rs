@@ -1,21 +1,53 @@
 use std::sync::Arc;
 
+use anyhow::anyhow;
+use clap::Parser;
 use willow_application_server_rs::{
-    db::pool::Pool, http::serve, state::WasState, trace::init_tracing, willow::worker::WorkerData,
+    cli::{Cli, Command},
+    db::pool::Pool,
+    http::serve,
+    import::import,
+    secret::SecretKey,
+    state::WasState,
+    trace::init_tracing,
+    willow::worker::WorkerData,
 };
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let command = cli.command().cloned();
+    let server_config = cli.server_config()?;
+
     init_tracing()?;
     tracing::info!("starting");
 
-    let worker_data = WorkerData::create().await?;
-    let db_pool = Pool::create().await?;
+    rustls::crypto::ring::default_provider()
+        .install_default()
+        .map_err(|_| anyhow!("failed to install rustls crypto provider"))?;
+
+    let secret_key = SecretKey::load_or_create(server_config.secret_key_file())?;
+    let db_pool = Pool::create().await?.with_secret_key(secret_key);
+    if let Err(e) = db_pool.encrypt_stored_secrets().await {
+        tracing::warn!("failed to encrypt stored secrets: {e}");
+    }
+
+    if let Some(Command::Import(args)) = command {
+        let report = import(&db_pool, &args).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let worker_data = WorkerData::create(&db_pool).await?;
     let state = WasState::new(db_pool, worker_data);
 
+    if let Err(e) = state.notify_queue().load(state.db_pool()).await {
+        tracing::warn!("failed to load pending notifications: {e}");
+    }
+
     tracing::debug!("{state:#?}");
 
-    serve(Arc::new(state)).await?;
+    serve(Arc::new(state), &server_config).await?;
 
     Ok(())
 }
@@ -0,0 +1,539 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{Context, anyhow};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    db::pool::Pool,
+    state::SharedState,
+    util::now_ms,
+    willow::messages::{WillowAction, WillowCommand, WillowNotify, WillowNotifyData},
+};
+
+/// Hostname to send a notification to all connected clients.
+pub const NOTIFY_HOSTNAME_ALL: &str = "all";
+
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pending notifications by hostname.
+#[derive(Debug, Default)]
+pub struct NotifyQueue {
+    notifications: RwLock<HashMap<String, Vec<WillowNotifyData>>>,
+}
+
+impl NotifyQueue {
+    /// Load pending notifications from the database.
+    ///
+    /// # Errors
+    /// - if we fail to get the notifications from the database
+    pub async fn load(&self, pool: &Pool) -> anyhow::Result<()> {
+        let mut notifications = self.notifications.write().await;
+        for (hostname, data) in pool.get_notifications().await? {
+            notifications.entry(hostname).or_default().push(data);
+        }
+
+        tracing::info!(
+            "loaded {} pending notifications",
+            notifications.values().map(Vec::len).sum::<usize>()
+        );
+
+        Ok(())
+    }
+
+    async fn add(&self, hostname: &str, data: WillowNotifyData) {
+        let mut notifications = self.notifications.write().await;
+        let pending = notifications.entry(hostname.to_string()).or_default();
+        pending.retain(|n| n.id != data.id);
+        pending.push(data);
+        pending.sort_by_key(|n| n.id);
+    }
+
+    async fn hostnames_with(&self, id: i64) -> Vec<String> {
+        self.notifications
+            .read()
+            .await
+            .iter()
+            .filter(|(_, pending)| pending.iter().any(|n| n.id == id))
+            .map(|(hostname, _)| hostname.clone())
+            .collect()
+    }
+
+    async fn due(&self, now: i64) -> Vec<(String, WillowNotifyData)> {
+        self.notifications
+            .read()
+            .await
+            .iter()
+            .filter_map(|(hostname, pending)| {
+                pending
+                    .first()
+                    .filter(|n| n.id <= now)
+                    .map(|n| (hostname.clone(), n.clone()))
+            })
+            .collect()
+    }
+
+    async fn remove(&self, hostname: &str, id: i64) {
+        let mut notifications = self.notifications.write().await;
+        if let Some(pending) = notifications.get_mut(hostname) {
+            pending.retain(|n| n.id != id);
+            if pending.is_empty() {
+                notifications.remove(hostname);
+            }
+        }
+    }
+}
+
+/// Queue a notification for the client with `hostname`, or for all connected, approved clients if `hostname` is
+/// [`NOTIFY_HOSTNAME_ALL`]. Notifications with `cancel` set cancel the notification with the same id instead.
+///
+/// # Errors
+/// - if the notification is a cancellation without id
+/// - if the notification has neither text nor audio URL
+/// - if the notification is for all clients and no clients are connected
+/// - if we fail to save the notification in the database
+pub async fn add_notification(
+    state: &SharedState,
+    hostname: &str,
+    mut data: WillowNotifyData,
+) -> anyhow::Result<()> {
+    let hostnames = if hostname == NOTIFY_HOSTNAME_ALL {
+        let hostnames: Vec<String> = state
+            .clients()
+            .read()
+            .await
+            .values()
+            .filter(|c| c.approved())
+            .filter_map(|c| c.hostname().clone())
+            .collect();
+
+        if hostnames.is_empty() {
+            return Err(anyhow!("no clients connected"));
+        }
+
+        hostnames
+    } else {
+        vec![hostname.to_string()]
+    };
+
+    if data.cancel {
+        if data.id <= 0 {
+            return Err(anyhow!("cancelling a notification requires its id"));
+        }
+        for hostname in hostnames {
+            cancel_notification(state, &hostname, data.id).await?;
+        }
+        return Ok(());
+    }
+
+    if data.text.is_none() && data.audio_url.is_none() {
+        return Err(anyhow!("notification requires text or audio_url"));
+    }
+
+    if data.id <= 0 {
+        data.id = now_ms();
+    }
+
+    for hostname in hostnames {
+        state
+            .db_pool()
+            .save_notification(&hostname, &data)
+            .await
+            .context("failed to save notification")?;
+        state.notify_queue().add(&hostname, data.clone()).await;
+    }
+
+    Ok(())
+}
+
+/// Handle a `notify_done` message: the notification was dismissed on the client, so it is removed from the queue
+/// and cancelled on all other clients.
+///
+/// # Errors
+/// - if we fail to delete the notification from the database
+pub async fn handle_notify_done(
+    state: &SharedState,
+    client_id: Uuid,
+    id: i64,
+) -> anyhow::Result<()> {
+    let hostname = {
+        let mut clients = state.clients().write().await;
+        let client = clients
+            .get_mut(&client_id)
+            .ok_or_else(|| anyhow!("client with id {client_id} not found"))?;
+        client.set_notification_id(None);
+        client.hostname().clone()
+    };
+
+    let Some(hostname) = hostname else {
+        return Ok(());
+    };
+
+    state.notify_queue().remove(&hostname, id).await;
+    state.db_pool().delete_notification(&hostname, id).await?;
+
+    let mut others = state.notify_queue().hostnames_with(id).await;
+    others.extend(
+        state
+            .clients()
+            .read()
+            .await
+            .values()
+            .filter(|c| c.notification_id() == Some(id))
+            .filter_map(|c| c.hostname().clone()),
+    );
+    others.sort();
+    others.dedup();
+
+    for other in others.iter().filter(|h| **h != hostname) {
+        cancel_notification(state, other, id).await?;
+    }
+
+    Ok(())
+}
+
+async fn cancel_notification(state: &SharedState, hostname: &str, id: i64) -> anyhow::Result<()> {
+    tracing::debug!("cancelling notification {id} for {hostname}");
+
+    state.notify_queue().remove(hostname, id).await;
+    state.db_pool().delete_notification(hostname, id).await?;
+
+    let Ok(client_id) = state.get_client_id_by_hostname(hostname).await else {
+        return Ok(());
+    };
+
+    let active = match state.clients().write().await.get_mut(&client_id) {
+        Some(client) if client.notification_id() == Some(id) => {
+            client.set_notification_id(None);
+            true
+        }
+        _ => false,
+    };
+
+    if active {
+        let data = WillowNotifyData {
+            cancel: true,
+            id,
+            ..Default::default()
+        };
+        send_notification(state, client_id, data).await?;
+    }
+
+    Ok(())
+}
+
+/// Deliver due notifications to connected, approved clients that are not already showing a notification.
+pub async fn notify_scheduler(state: SharedState) {
+    loop {
+        tokio::time::sleep(NOTIFY_INTERVAL).await;
+        deliver_notifications(&state, now_ms()).await;
+    }
+}
+
+async fn deliver_notifications(state: &SharedState, now: i64) {
+    for (hostname, data) in state.notify_queue().due(now).await {
+        let Ok(client_id) = state.get_client_id_by_hostname(&hostname).await else {
+            continue;
+        };
+
+        {
+            let mut clients = state.clients().write().await;
+            let Some(client) = clients.get_mut(&client_id) else {
+                continue;
+            };
+            // kept queued until the client is approved
+            if !client.approved() || client.notification_id().is_some() {
+                continue;
+            }
+            client.set_notification_id(Some(data.id));
+        }
+
+        tracing::debug!("sending notification {} to {hostname}", data.id);
+        if let Err(e) = send_notification(state, client_id, data).await {
+            tracing::error!("{e:#}");
+            if let Some(client) = state.clients().write().await.get_mut(&client_id) {
+                client.set_notification_id(None);
+            }
+        }
+    }
+}
+
+async fn send_notification(
+    state: &SharedState,
+    client_id: Uuid,
+    data: WillowNotifyData,
+) -> anyhow::Result<()> {
+    let msg_tx = state.get_msg_tx_by_client_id(client_id).await?;
+    let cmd = WillowCommand {
+        cmd: WillowAction::Notify(WillowNotify { data }),
+    };
+    let msg = serde_json::to_string(&cmd).context("failed to serialize WillowCommand")?;
+
+    msg_tx
+        .send(msg.into())
+        .await
+        .context(format!("failed to send notification to client {client_id}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+    use tokio::sync::mpsc::Receiver;
+    use uuid::Uuid;
+
+    use super::{
+        NOTIFY_HOSTNAME_ALL, NotifyQueue, add_notification, deliver_notifications,
+        handle_notify_done,
+    };
+    use crate::{
+        state::{SharedState, connect_test_client, create_test_state},
+        util::now_ms,
+        willow::{client::WillowClient, messages::WillowNotifyData},
+    };
+
+    async fn connect(state: &SharedState, hostname: &str) -> (Uuid, Receiver<Message>) {
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_hostname(hostname.to_string());
+        client.set_approved(true);
+        connect_test_client(state, client).await
+    }
+
+    fn notification(id: i64, text: &str, repeat: u32) -> WillowNotifyData {
+        WillowNotifyData {
+            id,
+            repeat,
+            text: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// The data of the notification sent to a client, if any.
+    fn received(msg_rx: &mut Receiver<Message>) -> Option<serde_json::Value> {
+        let msg = msg_rx.try_recv().ok()?;
+        let msg: serde_json::Value =
+            serde_json::from_str(msg.to_text().expect("message is not text"))
+                .expect("invalid message");
+        Some(msg["data"].clone())
+    }
+
+    async fn active(state: &SharedState, client_id: Uuid) -> Option<i64> {
+        state
+            .clients()
+            .read()
+            .await
+            .get(&client_id)
+            .and_then(WillowClient::notification_id)
+    }
+
+    async fn pending(state: &SharedState) -> Vec<(String, i64)> {
+        let mut pending: Vec<(String, i64)> = state
+            .db_pool()
+            .get_notifications()
+            .await
+            .expect("failed to get notifications")
+            .into_iter()
+            .map(|(hostname, data)| (hostname, data.id))
+            .collect();
+        pending.sort();
+        pending
+    }
+
+    #[tokio::test]
+    async fn test_notify_all() {
+        let state = create_test_state().await;
+        let (kitchen_id, mut kitchen_rx) = connect(&state, "willow-kitchen").await;
+        let (office_id, mut office_rx) = connect(&state, "willow-office").await;
+
+        let now = now_ms();
+        let later = now + 60_000;
+        add_notification(&state, NOTIFY_HOSTNAME_ALL, notification(now, "dinner", 3))
+            .await
+            .expect("failed to add notification");
+        add_notification(&state, "willow-kitchen", notification(later, "timer", 1))
+            .await
+            .expect("failed to add notification");
+        // queueing the same notification again replaces it
+        add_notification(&state, "willow-kitchen", notification(later, "timer", 2))
+            .await
+            .expect("failed to add notification");
+        assert_eq!(
+            pending(&state).await,
+            vec![
+                (String::from("willow-kitchen"), now),
+                (String::from("willow-kitchen"), later),
+                (String::from("willow-office"), now),
+            ]
+        );
+
+        deliver_notifications(&state, now).await;
+        for msg_rx in [&mut kitchen_rx, &mut office_rx] {
+            let data = received(msg_rx).expect("notification not sent");
+            assert_eq!(data["id"], now);
+            assert_eq!(data["repeat"], 3);
+            assert_eq!(data["text"], "dinner");
+        }
+        assert_eq!(active(&state, kitchen_id).await, Some(now));
+        assert_eq!(active(&state, office_id).await, Some(now));
+
+        // a client showing a notification gets no other one, even when it is due
+        deliver_notifications(&state, later).await;
+        assert!(received(&mut kitchen_rx).is_none());
+        assert!(received(&mut office_rx).is_none());
+
+        // dismissing the notification on one client cancels it on the others
+        handle_notify_done(&state, kitchen_id, now)
+            .await
+            .expect("failed to handle notify_done");
+        let data = received(&mut office_rx).expect("cancel not sent");
+        assert_eq!(data["id"], now);
+        assert_eq!(data["cancel"], true);
+        assert!(received(&mut kitchen_rx).is_none());
+        assert_eq!(active(&state, kitchen_id).await, None);
+        assert_eq!(active(&state, office_id).await, None);
+        assert_eq!(
+            pending(&state).await,
+            vec![(String::from("willow-kitchen"), later)]
+        );
+
+        deliver_notifications(&state, now).await;
+        assert!(received(&mut kitchen_rx).is_none());
+        deliver_notifications(&state, later).await;
+        let data = received(&mut kitchen_rx).expect("notification not sent");
+        assert_eq!(data["id"], later);
+        assert_eq!(data["repeat"], 2);
+        assert_eq!(active(&state, kitchen_id).await, Some(later));
+        assert!(received(&mut office_rx).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_notify_cancel() {
+        let state = create_test_state().await;
+        let (kitchen_id, mut kitchen_rx) = connect(&state, "willow-kitchen").await;
+        let (_office_id, mut office_rx) = connect(&state, "willow-office").await;
+
+        let now = now_ms();
+        add_notification(&state, NOTIFY_HOSTNAME_ALL, notification(now, "dinner", 1))
+            .await
+            .expect("failed to add notification");
+        add_notification(&state, "willow-office", notification(now + 1, "timer", 1))
+            .await
+            .expect("failed to add notification");
+        deliver_notifications(&state, now).await;
+        assert!(received(&mut kitchen_rx).is_some());
+        assert!(received(&mut office_rx).is_some());
+
+        let cancel = WillowNotifyData {
+            cancel: true,
+            ..Default::default()
+        };
+        assert!(
+            add_notification(&state, NOTIFY_HOSTNAME_ALL, cancel.clone())
+                .await
+                .is_err()
+        );
+
+        // the shown notification is cancelled on the client, the queued one is only removed
+        add_notification(
+            &state,
+            NOTIFY_HOSTNAME_ALL,
+            WillowNotifyData {
+                id: now,
+                ..cancel.clone()
+            },
+        )
+        .await
+        .expect("failed to cancel notification");
+        add_notification(
+            &state,
+            "willow-office",
+            WillowNotifyData {
+                id: now + 1,
+                ..cancel
+            },
+        )
+        .await
+        .expect("failed to cancel notification");
+
+        for msg_rx in [&mut kitchen_rx, &mut office_rx] {
+            let data = received(msg_rx).expect("cancel not sent");
+            assert_eq!(data["id"], now);
+            assert_eq!(data["cancel"], true);
+            assert!(received(msg_rx).is_none());
+        }
+        assert_eq!(active(&state, kitchen_id).await, None);
+        assert!(pending(&state).await.is_empty());
+
+        deliver_notifications(&state, now + 1).await;
+        assert!(received(&mut office_rx).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_notify_pending_client() {
+        let state = create_test_state().await;
+        let (client_id, mut msg_rx) = connect(&state, "willow-kitchen").await;
+        if let Some(client) = state.clients().write().await.get_mut(&client_id) {
+            client.set_approved(false);
+        }
+
+        let now = now_ms();
+        assert!(
+            add_notification(&state, NOTIFY_HOSTNAME_ALL, notification(now, "dinner", 1))
+                .await
+                .is_err()
+        );
+        add_notification(&state, "willow-kitchen", notification(now, "dinner", 1))
+            .await
+            .expect("failed to add notification");
+
+        // the notification stays queued until the client is approved
+        deliver_notifications(&state, now).await;
+        assert!(received(&mut msg_rx).is_none());
+        assert_eq!(active(&state, client_id).await, None);
+
+        if let Some(client) = state.clients().write().await.get_mut(&client_id) {
+            client.set_approved(true);
+        }
+        deliver_notifications(&state, now).await;
+        assert_eq!(
+            received(&mut msg_rx).expect("notification not sent")["id"],
+            now
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notify_reload() {
+        let state = create_test_state().await;
+        let now = now_ms();
+        add_notification(&state, "willow-kitchen", notification(now + 1, "timer", 1))
+            .await
+            .expect("failed to add notification");
+        add_notification(&state, "willow-kitchen", notification(now, "dinner", 1))
+            .await
+            .expect("failed to add notification");
+
+        // a restart loads the pending notifications of hosts that are not connected, in order
+        let queue = NotifyQueue::default();
+        queue
+            .load(state.db_pool())
+            .await
+            .expect("failed to load notifications");
+        let due: Vec<(String, i64)> = queue
+            .due(now + 1)
+            .await
+            .into_iter()
+            .map(|(hostname, data)| (hostname, data.id))
+            .collect();
+        assert_eq!(due, vec![(String::from("willow-kitchen"), now)]);
+        assert!(queue.due(now - 1).await.is_empty());
+
+        queue.remove("willow-kitchen", now).await;
+        assert_eq!(queue.due(now + 1).await[0].1.id, now + 1);
+        queue.remove("willow-kitchen", now + 1).await;
+        assert!(queue.notifications.read().await.is_empty());
+    }
+}
@@ -0,0 +1,756 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tokio::{fs, sync::Mutex};
+use uuid::Uuid;
+
+use crate::{
+    config::WasOtaSource,
+    db::ota::WillowOtaHistory,
+    enrollment::require_approved,
+    state::SharedState,
+    util::now_ms,
+    willow::{
+        client::WillowClientOta,
+        messages::{
+            WillowAction, WillowCommand, WillowMsgOtaStatus, WillowOtaStart, WillowOtaStatus,
+        },
+        release::WillowReleaseAsset,
+    },
+};
+
+const DEFAULT_OTA_DIR: &str = "ota";
+
+/// Local cache of OTA firmware binaries, stored as `{dir}/{version}/{platform}.bin`.
+#[derive(Debug)]
+pub struct OtaCache {
+    client: reqwest::Client,
+    dir: PathBuf,
+    download_lock: Mutex<()>,
+}
+
+impl OtaCache {
+    #[must_use]
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            dir,
+            download_lock: Mutex::new(()),
+        }
+    }
+
+    /// Create an `OtaCache` in the directory from `WAS_OTA_DIR`, or the default if unset.
+    #[must_use]
+    pub fn from_env() -> Self {
+        let dir = env::var("WAS_OTA_DIR").unwrap_or(String::from(DEFAULT_OTA_DIR));
+
+        Self::new(PathBuf::from(dir))
+    }
+
+    /// # Errors
+    /// - if `version` or `platform` cannot be used as a path component
+    pub fn path(&self, version: &str, platform: &str) -> anyhow::Result<PathBuf> {
+        for component in [version, platform] {
+            if component.is_empty() || component.starts_with('.') || component.contains(['/', '\\'])
+            {
+                return Err(anyhow!("invalid OTA path component {component}"));
+            }
+        }
+
+        Ok(self.dir.join(version).join(format!("{platform}.bin")))
+    }
+
+    /// Make sure the OTA build described by `asset` is in the cache, downloading it if it is missing or does not match
+    /// the size and checksum of the asset, and return its path.
+    ///
+    /// # Errors
+    /// - if `version` or `platform` cannot be used as a path component
+    /// - if the download fails
+    /// - if the size or checksum of the download does not match the asset
+    /// - if the download cannot be written to the cache directory
+    pub async fn ensure(
+        &self,
+        version: &str,
+        platform: &str,
+        asset: &WillowReleaseAsset,
+    ) -> anyhow::Result<PathBuf> {
+        let path = self.path(version, platform)?;
+
+        let _lock = self.download_lock.lock().await;
+
+        if self.read_verified(&path, asset).await?.is_some() {
+            return Ok(path);
+        }
+
+        tracing::info!(
+            "downloading OTA build {version} for {platform} from {}",
+            asset.browser_download_url
+        );
+
+        let response = self
+            .client
+            .get(&asset.browser_download_url)
+            .send()
+            .await?
+            .error_for_status()?;
+        let bytes = response.bytes().await?;
+
+        verify(asset, &bytes)?;
+
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow!("invalid OTA path {}", path.display()))?;
+        fs::create_dir_all(parent).await?;
+
+        let tmp = path.with_extension("bin.part");
+        fs::write(&tmp, &bytes).await?;
+        fs::rename(&tmp, &path).await?;
+
+        Ok(path)
+    }
+
+    /// Get the cached OTA build described by `asset`, `None` if it is not cached or does not match the asset.
+    ///
+    /// # Errors
+    /// - if `version` or `platform` cannot be used as a path component
+    /// - if the cached file exists but cannot be read
+    pub async fn get(
+        &self,
+        version: &str,
+        platform: &str,
+        asset: &WillowReleaseAsset,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = self.path(version, platform)?;
+
+        self.read_verified(&path, asset).await
+    }
+
+    async fn read_verified(
+        &self,
+        path: &Path,
+        asset: &WillowReleaseAsset,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let bytes = match fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Err(e) = verify(asset, &bytes) {
+            tracing::warn!("ignoring cached OTA build {}: {e}", path.display());
+            return Ok(None);
+        }
+
+        Ok(Some(bytes))
+    }
+}
+
+/// Build the URL devices use to fetch an OTA build from WAS, based on the WAS URL from the NVS config,
+/// e.g. `ws://was.local:8502/ws` becomes `http://was.local:8502/api/ota/{version}/{platform}.bin`.
+///
+/// # Errors
+/// - if the WAS URL is not a valid websocket URL
+pub fn was_ota_url(was_url: &str, version: &str, platform: &str) -> anyhow::Result<String> {
+    let mut url = Url::parse(was_url).context("invalid WAS URL in NVS config")?;
+
+    let scheme = match url.scheme() {
+        "ws" => "http",
+        "wss" => "https",
+        scheme => return Err(anyhow!("unsupported WAS URL scheme {scheme}")),
+    };
+    url.set_scheme(scheme)
+        .map_err(|()| anyhow!("failed to set scheme of WAS URL"))?;
+    url.set_query(None);
+    url.path_segments_mut()
+        .map_err(|()| anyhow!("invalid WAS URL"))?
+        .clear()
+        .extend(["api", "ota", version, &format!("{platform}.bin")]);
+
+    Ok(url.to_string())
+}
+
+/// Get the URL a client should fetch the OTA build described by `asset` from, depending on the configured
+/// [`WasOtaSource`]. When WAS serves the build, it is cached first.
+///
+/// # Errors
+/// - if we fail to get the WAS config or NVS config from the database
+/// - if we fail to cache the OTA build
+/// - if the WAS URL in the NVS config is invalid
+pub async fn ota_url(
+    state: &SharedState,
+    version: &str,
+    platform: &str,
+    asset: &WillowReleaseAsset,
+) -> anyhow::Result<String> {
+    match state
+        .db_pool()
+        .get_was_config_or_default()
+        .await
+        .ota_source()
+    {
+        WasOtaSource::Upstream => Ok(asset.browser_download_url.clone()),
+        WasOtaSource::Was => {
+            state
+                .ota_cache()
+                .ensure(version, platform, asset)
+                .await
+                .context("failed to cache OTA build")?;
+            let nvs = state
+                .db_pool()
+                .get_willow_nvs()
+                .await
+                .context("failed to get WAS URL from NVS config")?;
+            was_ota_url(nvs.was.url(), version, platform)
+        }
+    }
+}
+
+/// Send an `ota_start` command for release `version` to the client and start tracking the OTA.
+///
+/// # Errors
+/// - if the client is not approved
+/// - if the client is not connected or its platform is unknown
+/// - if there is no OTA build of `version` for the platform of the client
+/// - if we fail to get the OTA URL
+/// - if we fail to send the command to the client or to save the OTA in the database
+pub async fn send_ota_start(
+    state: &SharedState,
+    client_id: Uuid,
+    version: &str,
+) -> anyhow::Result<()> {
+    require_approved(state, client_id).await?;
+
+    let platform = state
+        .clients()
+        .read()
+        .await
+        .get(&client_id)
+        .and_then(|c| c.platform().clone())
+        .ok_or_else(|| anyhow!("platform of client {client_id} is unknown"))?;
+
+    let worker_data = state.worker_data().await;
+    let asset = worker_data
+        .ota_asset(version, &platform)
+        .ok_or_else(|| anyhow!("no OTA build of release {version} for platform {platform}"))?;
+
+    let cmd = WillowCommand {
+        cmd: WillowAction::OtaStart(WillowOtaStart {
+            ota_url: ota_url(state, version, &platform, asset).await?,
+        }),
+    };
+    let msg = serde_json::to_string(&cmd).context("failed to serialize WillowCommand")?;
+
+    state
+        .get_msg_tx_by_client_id(client_id)
+        .await?
+        .send(msg.into())
+        .await
+        .context(format!("failed to send ota_start to client {client_id}"))?;
+
+    start_ota_tracking(state, client_id, version).await
+}
+
+/// Record an OTA to `version` that was sent to the client, so its progress and outcome can be tracked.
+///
+/// # Errors
+/// - if the client is not found or its MAC address is unknown
+/// - if we fail to save the OTA in the database
+pub async fn start_ota_tracking(
+    state: &SharedState,
+    client_id: Uuid,
+    version: &str,
+) -> anyhow::Result<()> {
+    let (mac_addr, hostname, version_from) = {
+        let clients = state.clients().read().await;
+        let client = clients
+            .get(&client_id)
+            .ok_or_else(|| anyhow!("client with id {client_id} not found"))?;
+        (
+            client.mac_addr().clone(),
+            client.hostname().clone(),
+            client.version().to_string(),
+        )
+    };
+    let mac_addr =
+        mac_addr.ok_or_else(|| anyhow!("MAC address of client {client_id} is unknown"))?;
+
+    let now = now_ms();
+    let history = WillowOtaHistory {
+        id: Uuid::new_v4().to_string(),
+        mac_addr,
+        hostname,
+        version_from,
+        version_to: version.to_string(),
+        status: WillowOtaStatus::Start.as_ref().to_string(),
+        started_at: now,
+        updated_at: now,
+    };
+    state.db_pool().save_ota_history(&history).await?;
+
+    if let Some(client) = state.clients().write().await.get_mut(&client_id) {
+        client.set_ota(Some(WillowClientOta {
+            history_id: Some(history.id),
+            progress: None,
+            status: WillowOtaStatus::Start,
+            version: Some(history.version_to),
+        }));
+    }
+
+    Ok(())
+}
+
+/// Handle an `ota_status` message. Progress is only kept in memory, status changes are also saved in the OTA
+/// history.
+///
+/// # Errors
+/// - if the client is not found
+/// - if we fail to update the OTA in the database
+pub async fn handle_ota_status(
+    state: &SharedState,
+    client_id: Uuid,
+    msg: &WillowMsgOtaStatus,
+) -> anyhow::Result<()> {
+    let (history_id, changed) = {
+        let mut clients = state.clients().write().await;
+        let client = clients
+            .get_mut(&client_id)
+            .ok_or_else(|| anyhow!("client with id {client_id} not found"))?;
+
+        let mut ota = client.ota().clone().unwrap_or(WillowClientOta {
+            history_id: None,
+            progress: None,
+            status: msg.status,
+            version: None,
+        });
+        let changed = ota.status != msg.status;
+        ota.status = msg.status;
+        if msg.progress.is_some() {
+            ota.progress = msg.progress;
+        }
+        let history_id = ota.history_id.clone();
+        client.set_ota(Some(ota));
+
+        (history_id, changed)
+    };
+
+    if changed && let Some(history_id) = history_id {
+        state
+            .db_pool()
+            .update_ota_history_status(&history_id, msg.status.as_ref(), now_ms())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Resolve the pending OTA of a client that (re)connected: the OTA succeeded if the client now runs the version
+/// it was updated to, otherwise the client aborted or rolled back the OTA.
+///
+/// # Errors
+/// - if we fail to get or update the OTA in the database
+pub async fn resolve_pending_ota(
+    state: &SharedState,
+    client_id: Uuid,
+    mac_addr: &str,
+) -> anyhow::Result<()> {
+    let Some(pending) = state.db_pool().get_pending_ota(mac_addr).await? else {
+        return Ok(());
+    };
+
+    let mut clients = state.clients().write().await;
+    let Some(client) = clients.get_mut(&client_id) else {
+        return Ok(());
+    };
+
+    let status = if client.version() == pending.version_to {
+        tracing::info!("OTA of {mac_addr} to {} succeeded", pending.version_to);
+        WillowOtaStatus::Success
+    } else {
+        tracing::warn!(
+            "OTA of {mac_addr} to {} failed, client is running {}",
+            pending.version_to,
+            client.version()
+        );
+        WillowOtaStatus::Failed
+    };
+    client.set_ota(Some(WillowClientOta {
+        history_id: Some(pending.id.clone()),
+        progress: None,
+        status,
+        version: Some(pending.version_to),
+    }));
+    drop(clients);
+
+    state
+        .db_pool()
+        .update_ota_history_status(&pending.id, status.as_ref(), now_ms())
+        .await
+}
+
+fn verify(asset: &WillowReleaseAsset, bytes: &[u8]) -> anyhow::Result<()> {
+    if let Some(size) = asset.size
+        && size != bytes.len() as u64
+    {
+        return Err(anyhow!(
+            "size of OTA download {} does not match asset size {size}",
+            bytes.len()
+        ));
+    }
+
+    if let Some(digest) = &asset.digest {
+        let expected = digest.strip_prefix("sha256:").unwrap_or(digest);
+        let actual = format!("{:x}", Sha256::digest(bytes));
+        if !expected.eq_ignore_ascii_case(&actual) {
+            return Err(anyhow!(
+                "checksum of OTA download {actual} does not match asset checksum {expected}"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use eui48::MacAddress;
+    use serde_json::Map;
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::{
+        OtaCache, handle_ota_status, resolve_pending_ota, start_ota_tracking, was_ota_url,
+    };
+    use crate::{
+        db::ota::WillowOtaHistory,
+        state::{SharedState, connect_test_client, create_test_state},
+        willow::{
+            client::{WillowClient, WillowClientOta},
+            messages::{WillowMsgOtaStatus, WillowOtaStatus},
+            release::WillowReleaseAsset,
+        },
+    };
+
+    const BUILD: &[u8] = b"willow ota build";
+
+    const MAC_ADDR: &str = "7c:df:a1:e7:a8:98";
+
+    async fn connect(state: &SharedState, version: &str) -> Uuid {
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            &format!("Willow/{version}"),
+        );
+        client.set_hostname(String::from("willow-kitchen"));
+        client.set_mac_addr(MacAddress::parse_str(MAC_ADDR).expect("invalid MAC address"));
+        let (client_id, _msg_rx) = connect_test_client(state, client).await;
+
+        client_id
+    }
+
+    async fn ota_status(
+        state: &SharedState,
+        client_id: Uuid,
+        status: WillowOtaStatus,
+        progress: Option<u8>,
+    ) {
+        handle_ota_status(state, client_id, &WillowMsgOtaStatus { progress, status })
+            .await
+            .expect("failed to handle ota_status");
+    }
+
+    async fn client_ota(state: &SharedState, client_id: Uuid) -> WillowClientOta {
+        state
+            .clients()
+            .read()
+            .await
+            .get(&client_id)
+            .and_then(|c| c.ota().clone())
+            .expect("client has no OTA")
+    }
+
+    async fn ota_history(state: &SharedState) -> Vec<WillowOtaHistory> {
+        state
+            .db_pool()
+            .get_ota_history(Some(MAC_ADDR))
+            .await
+            .expect("failed to get OTA history")
+    }
+
+    /// Start an OTA from 0.3.0 to 0.3.1 and report progress until the client reboots, then reconnect running
+    /// `version` and return the resolved OTA.
+    async fn ota_reconnect(state: &SharedState, version: &str) -> WillowClientOta {
+        let client_id = connect(state, "0.3.0").await;
+        start_ota_tracking(state, client_id, "0.3.1")
+            .await
+            .expect("failed to start OTA tracking");
+        ota_status(state, client_id, WillowOtaStatus::Progress, Some(20)).await;
+        state.delete_client(client_id).await;
+
+        let client_id = connect(state, version).await;
+        resolve_pending_ota(state, client_id, MAC_ADDR)
+            .await
+            .expect("failed to resolve pending OTA");
+
+        client_ota(state, client_id).await
+    }
+
+    #[tokio::test]
+    async fn test_handle_ota_status() {
+        let state = create_test_state().await;
+        let client_id = connect(&state, "0.3.0").await;
+
+        start_ota_tracking(&state, client_id, "0.3.1")
+            .await
+            .expect("failed to start OTA tracking");
+        let history = ota_history(&state).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, "start");
+        assert_eq!(history[0].version_from, "0.3.0");
+        assert_eq!(history[0].version_to, "0.3.1");
+        assert_eq!(history[0].hostname.as_deref(), Some("willow-kitchen"));
+
+        ota_status(&state, client_id, WillowOtaStatus::Progress, Some(10)).await;
+        let updated_at = ota_history(&state).await[0].updated_at;
+        ota_status(&state, client_id, WillowOtaStatus::Progress, Some(50)).await;
+        ota_status(&state, client_id, WillowOtaStatus::Progress, None).await;
+
+        // progress is only kept in memory
+        let ota = client_ota(&state, client_id).await;
+        assert_eq!(ota.status, WillowOtaStatus::Progress);
+        assert_eq!(ota.progress, Some(50));
+        assert_eq!(ota.version.as_deref(), Some("0.3.1"));
+        let history = ota_history(&state).await;
+        assert_eq!(history[0].status, "progress");
+        assert_eq!(history[0].updated_at, updated_at);
+
+        ota_status(&state, client_id, WillowOtaStatus::Done, None).await;
+        assert_eq!(
+            client_ota(&state, client_id).await.status,
+            WillowOtaStatus::Done
+        );
+        assert_eq!(ota_history(&state).await[0].status, "done");
+
+        // an OTA that was not started by WAS is only tracked in memory
+        state.delete_client(client_id).await;
+        let client_id = connect(&state, "0.3.1").await;
+        ota_status(&state, client_id, WillowOtaStatus::Failed, None).await;
+        let ota = client_ota(&state, client_id).await;
+        assert_eq!(ota.status, WillowOtaStatus::Failed);
+        assert!(ota.history_id.is_none());
+        assert_eq!(ota_history(&state).await[0].status, "done");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_pending_ota_success() {
+        let state = create_test_state().await;
+
+        let ota = ota_reconnect(&state, "0.3.1").await;
+        assert_eq!(ota.status, WillowOtaStatus::Success);
+        assert_eq!(ota.version.as_deref(), Some("0.3.1"));
+
+        let history = ota_history(&state).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, "success");
+        assert_eq!(ota.history_id.as_ref(), Some(&history[0].id));
+
+        // the OTA is resolved only once
+        let client_id = connect(&state, "0.3.0").await;
+        resolve_pending_ota(&state, client_id, MAC_ADDR)
+            .await
+            .expect("failed to resolve pending OTA");
+        assert!(
+            state
+                .clients()
+                .read()
+                .await
+                .get(&client_id)
+                .is_some_and(|c| c.ota().is_none())
+        );
+        assert_eq!(ota_history(&state).await[0].status, "success");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_pending_ota_failed() {
+        let state = create_test_state().await;
+
+        // reconnecting mid-OTA with the old version means the OTA was aborted or rolled back
+        let ota = ota_reconnect(&state, "0.3.0").await;
+        assert_eq!(ota.status, WillowOtaStatus::Failed);
+
+        let history = ota_history(&state).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, "failed");
+        assert!(
+            state
+                .db_pool()
+                .get_pending_ota(MAC_ADDR)
+                .await
+                .expect("failed to get pending OTA")
+                .is_none()
+        );
+    }
+
+    async fn mock_build(server: &MockServer, body: &[u8], expect: u64) {
+        Mock::given(method("GET"))
+            .and(path("/willow-ota-ESP32-S3-BOX.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+            .expect(expect)
+            .mount(server)
+            .await;
+    }
+
+    fn build_asset(server: &MockServer) -> WillowReleaseAsset {
+        WillowReleaseAsset {
+            browser_download_url: format!("{}/willow-ota-ESP32-S3-BOX.bin", server.uri()),
+            build_type: Some(String::from("ota")),
+            digest: Some(format!("sha256:{:x}", Sha256::digest(BUILD))),
+            name: String::from("willow-ota-ESP32-S3-BOX.bin"),
+            platform: Some(String::from("ESP32-S3-BOX")),
+            size: Some(BUILD.len() as u64),
+            other: Map::new(),
+        }
+    }
+
+    fn test_cache() -> OtaCache {
+        OtaCache::new(env::temp_dir().join(format!("was-ota-{}", Uuid::new_v4())))
+    }
+
+    #[tokio::test]
+    async fn test_ota_cache() {
+        let server = MockServer::start().await;
+        mock_build(&server, BUILD, 2).await;
+        let asset = build_asset(&server);
+        let cache = test_cache();
+
+        assert!(
+            cache
+                .get("0.3.1", "ESP32-S3-BOX", &asset)
+                .await
+                .expect("failed to get OTA build")
+                .is_none()
+        );
+
+        let path = cache
+            .ensure("0.3.1", "ESP32-S3-BOX", &asset)
+            .await
+            .expect("failed to cache OTA build");
+        assert_eq!(fs::read(&path).expect("failed to read OTA build"), BUILD);
+
+        // a cache hit does not download the build again
+        cache
+            .ensure("0.3.1", "ESP32-S3-BOX", &asset)
+            .await
+            .expect("failed to cache OTA build");
+        assert_eq!(
+            cache
+                .get("0.3.1", "ESP32-S3-BOX", &asset)
+                .await
+                .expect("failed to get OTA build")
+                .as_deref(),
+            Some(BUILD)
+        );
+
+        // a corrupted build of the right size is not served, but downloaded again
+        let mut corrupted = BUILD.to_vec();
+        corrupted[0] ^= 0xff;
+        fs::write(&path, corrupted).expect("failed to corrupt OTA build");
+        assert!(
+            cache
+                .get("0.3.1", "ESP32-S3-BOX", &asset)
+                .await
+                .expect("failed to get OTA build")
+                .is_none()
+        );
+        cache
+            .ensure("0.3.1", "ESP32-S3-BOX", &asset)
+            .await
+            .expect("failed to cache OTA build");
+        assert_eq!(fs::read(&path).expect("failed to read OTA build"), BUILD);
+
+        fs::remove_dir_all(&cache.dir).expect("failed to remove temp dir");
+    }
+
+    #[tokio::test]
+    async fn test_ota_cache_bad_download() {
+        let server = MockServer::start().await;
+        let mut bad = BUILD.to_vec();
+        bad[0] ^= 0xff;
+        mock_build(&server, &bad, 2).await;
+        let asset = build_asset(&server);
+        let cache = test_cache();
+
+        let err = cache
+            .ensure("0.3.1", "ESP32-S3-BOX", &asset)
+            .await
+            .expect_err("cached OTA build with wrong checksum");
+        assert!(err.to_string().contains("checksum"), "{err}");
+
+        let err = cache
+            .ensure(
+                "0.3.1",
+                "ESP32-S3-BOX",
+                &WillowReleaseAsset {
+                    size: Some(1),
+                    ..asset.clone()
+                },
+            )
+            .await
+            .expect_err("cached OTA build with wrong size");
+        assert!(err.to_string().contains("size"), "{err}");
+
+        assert!(
+            !cache
+                .path("0.3.1", "ESP32-S3-BOX")
+                .expect("invalid OTA path")
+                .exists()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ota_cache_path() {
+        let server = MockServer::start().await;
+        mock_build(&server, BUILD, 0).await;
+        let asset = build_asset(&server);
+        let cache = test_cache();
+
+        for (version, platform) in [
+            ("../0.3.1", "ESP32-S3-BOX"),
+            ("..", "ESP32-S3-BOX"),
+            ("0.3.1", "../ESP32-S3-BOX"),
+            ("0.3.1", "ESP32\\S3"),
+            ("", "ESP32-S3-BOX"),
+        ] {
+            assert!(
+                cache.ensure(version, platform, &asset).await.is_err(),
+                "{version} {platform}"
+            );
+            assert!(cache.get(version, platform, &asset).await.is_err());
+        }
+        assert!(!cache.dir.exists());
+    }
+
+    #[test]
+    fn test_was_ota_url() {
+        assert_eq!(
+            was_ota_url("ws://was.local:8502/ws", "0.3.1", "ESP32-S3-BOX-3")
+                .expect("failed to build OTA URL"),
+            "http://was.local:8502/api/ota/0.3.1/ESP32-S3-BOX-3.bin"
+        );
+        assert_eq!(
+            was_ota_url("wss://was.example.com/ws", "0.3.1", "ESP32-S3-BOX")
+                .expect("failed to build OTA URL"),
+            "https://was.example.com/api/ota/0.3.1/ESP32-S3-BOX.bin"
+        );
+        assert!(was_ota_url("http://was.local/ws", "0.3.1", "ESP32-S3-BOX").is_err());
+    }
+}
@@ -0,0 +1,719 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{
+    ota::send_ota_start,
+    state::SharedState,
+    util::now_ms,
+    willow::{client::WillowClient, messages::WillowOtaStatus},
+};
+
+const ROLLOUT_DEFAULT_BATCH_SIZE: usize = 1;
+const ROLLOUT_DEFAULT_TIMEOUT: u64 = 600;
+const ROLLOUT_MAX_TIMEOUT: u64 = 86_400;
+const ROLLOUT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Clients targeted by a rollout, e.g. `{"type": "label", "pattern": "kitchen*"}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase", tag = "type")]
+pub enum RolloutTarget {
+    All,
+    Label { pattern: String },
+    Platform { platform: String },
+}
+
+impl RolloutTarget {
+    fn matches(&self, client: &WillowClient) -> bool {
+        match self {
+            Self::All => true,
+            Self::Label { pattern } => client
+                .label()
+                .as_ref()
+                .is_some_and(|l| glob_match(pattern, l)),
+            Self::Platform { platform } => client.platform().as_ref() == Some(platform),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RolloutRequest {
+    /// Number of clients updated at the same time.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    pub target: RolloutTarget,
+    /// Seconds to wait for a client to reconnect with the new version.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    pub version: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RolloutStatus {
+    Cancelled,
+    Completed,
+    /// A client in the last batch failed to update, the remaining clients were not updated.
+    Halted,
+    Running,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RolloutClientStatus {
+    Failed,
+    Pending,
+    /// Client already runs the version of the rollout.
+    Skipped,
+    Success,
+    Updating,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RolloutClient {
+    pub error: Option<String>,
+    pub hostname: String,
+    pub mac_addr: String,
+    pub status: RolloutClientStatus,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Rollout {
+    pub batch_size: usize,
+    pub clients: Vec<RolloutClient>,
+    pub id: Uuid,
+    pub started_at: i64,
+    pub status: RolloutStatus,
+    pub target: RolloutTarget,
+    pub timeout: u64,
+    pub version: String,
+}
+
+impl Rollout {
+    /// Create a rollout for the connected, approved clients matching the target of `request`.
+    ///
+    /// # Errors
+    /// - if the batch size is 0
+    /// - if the timeout is 0 or longer than a day
+    /// - if no connected client matches the target
+    pub fn new(request: RolloutRequest, clients: &[WillowClient]) -> anyhow::Result<Self> {
+        if request.batch_size == 0 {
+            return Err(anyhow!("batch_size must be at least 1"));
+        }
+        if !(1..=ROLLOUT_MAX_TIMEOUT).contains(&request.timeout) {
+            return Err(anyhow!(
+                "timeout must be between 1 and {ROLLOUT_MAX_TIMEOUT} seconds"
+            ));
+        }
+
+        let clients: Vec<RolloutClient> = clients
+            .iter()
+            .filter(|c| c.approved() && request.target.matches(c))
+            .filter_map(|c| {
+                let status = if c.version() == request.version {
+                    RolloutClientStatus::Skipped
+                } else {
+                    RolloutClientStatus::Pending
+                };
+                Some(RolloutClient {
+                    error: None,
+                    hostname: c.hostname().clone()?,
+                    mac_addr: c.mac_addr().clone()?,
+                    status,
+                })
+            })
+            .collect();
+
+        if clients.is_empty() {
+            return Err(anyhow!("no connected clients match the rollout target"));
+        }
+
+        Ok(Self {
+            batch_size: request.batch_size,
+            clients,
+            id: Uuid::new_v4(),
+            started_at: now_ms(),
+            status: RolloutStatus::Running,
+            target: request.target,
+            timeout: request.timeout,
+            version: request.version,
+        })
+    }
+
+    fn next_batch(&self) -> Vec<usize> {
+        self.clients
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.status == RolloutClientStatus::Pending)
+            .map(|(i, _)| i)
+            .take(self.batch_size)
+            .collect()
+    }
+}
+
+/// Start a rollout in the background.
+///
+/// # Errors
+/// - if another rollout is running
+/// - if the rollout cannot be created from `request`
+pub async fn start_rollout(
+    state: &SharedState,
+    request: RolloutRequest,
+) -> anyhow::Result<Rollout> {
+    let clients: Vec<WillowClient> = state.clients().read().await.values().cloned().collect();
+
+    let mut current = state.rollout().write().await;
+    if current
+        .as_ref()
+        .is_some_and(|r| r.status == RolloutStatus::Running)
+    {
+        return Err(anyhow!("another rollout is running"));
+    }
+
+    let rollout = Rollout::new(request, &clients)?;
+    tracing::info!(
+        "starting rollout {} of {} to {} clients",
+        rollout.id,
+        rollout.version,
+        rollout.clients.len()
+    );
+    *current = Some(rollout.clone());
+    drop(current);
+
+    tokio::spawn(run_rollout(Arc::clone(state), rollout.id));
+
+    Ok(rollout)
+}
+
+/// Cancel the running rollout. Clients that are being updated finish their OTA, no further batches are started.
+///
+/// # Errors
+/// - if no rollout is running
+pub async fn cancel_rollout(state: &SharedState) -> anyhow::Result<()> {
+    match state.rollout().write().await.as_mut() {
+        Some(rollout) if rollout.status == RolloutStatus::Running => {
+            tracing::info!("cancelling rollout {}", rollout.id);
+            rollout.status = RolloutStatus::Cancelled;
+            Ok(())
+        }
+        _ => Err(anyhow!("no rollout running")),
+    }
+}
+
+async fn run_rollout(state: SharedState, id: Uuid) {
+    loop {
+        let (batch, version, timeout) = {
+            let mut current = state.rollout().write().await;
+            let Some(rollout) = current
+                .as_mut()
+                .filter(|r| r.id == id && r.status == RolloutStatus::Running)
+            else {
+                return;
+            };
+
+            let batch = rollout.next_batch();
+            if batch.is_empty() {
+                tracing::info!("rollout {id} completed");
+                rollout.status = RolloutStatus::Completed;
+                return;
+            }
+            for i in &batch {
+                rollout.clients[*i].status = RolloutClientStatus::Updating;
+            }
+
+            let batch: Vec<(usize, RolloutClient)> = batch
+                .into_iter()
+                .map(|i| (i, rollout.clients[i].clone()))
+                .collect();
+
+            (
+                batch,
+                rollout.version.clone(),
+                Duration::from_secs(rollout.timeout),
+            )
+        };
+
+        let results = join_all(
+            batch
+                .iter()
+                .map(|(_, client)| update_client(&state, id, client, &version, timeout)),
+        )
+        .await;
+
+        let mut current = state.rollout().write().await;
+        let Some(rollout) = current.as_mut().filter(|r| r.id == id) else {
+            return;
+        };
+
+        let mut failed = false;
+        for ((i, _), result) in batch.iter().zip(results) {
+            let client = &mut rollout.clients[*i];
+            match result {
+                Ok(()) => client.status = RolloutClientStatus::Success,
+                Err(e) => {
+                    tracing::warn!("rollout {id}: failed to update {}: {e:#}", client.hostname);
+                    client.status = RolloutClientStatus::Failed;
+                    client.error = Some(format!("{e:#}"));
+                    failed = true;
+                }
+            }
+        }
+
+        if failed && rollout.status == RolloutStatus::Running {
+            tracing::warn!("halting rollout {id}");
+            rollout.status = RolloutStatus::Halted;
+        }
+    }
+}
+
+async fn update_client(
+    state: &SharedState,
+    id: Uuid,
+    client: &RolloutClient,
+    version: &str,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let client_id = state.get_client_id_by_hostname(&client.hostname).await?;
+    send_ota_start(state, client_id, version).await?;
+
+    let deadline = Instant::now()
+        .checked_add(timeout)
+        .ok_or_else(|| anyhow!("invalid timeout {timeout:?}"))?;
+    while Instant::now() < deadline {
+        tokio::time::sleep(ROLLOUT_POLL_INTERVAL).await;
+
+        if state
+            .rollout()
+            .read()
+            .await
+            .as_ref()
+            .is_none_or(|r| r.id != id || r.status != RolloutStatus::Running)
+        {
+            return Err(anyhow!("rollout cancelled"));
+        }
+
+        let status = state
+            .clients()
+            .read()
+            .await
+            .values()
+            .filter(|c| c.mac_addr().as_ref() == Some(&client.mac_addr))
+            .filter_map(|c| c.ota().as_ref())
+            .filter(|o| o.version.as_deref() == Some(version))
+            .map(|o| o.status)
+            .find(|s| matches!(s, WillowOtaStatus::Failed | WillowOtaStatus::Success));
+
+        match status {
+            Some(WillowOtaStatus::Success) => return Ok(()),
+            Some(_) => return Err(anyhow!("OTA failed")),
+            None => {}
+        }
+    }
+
+    Err(anyhow!(
+        "client did not reconnect with version {version} within {timeout:?}"
+    ))
+}
+
+/// Match `value` against a glob `pattern`, where `*` matches any number of characters and `?` matches one.
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bv)) => {
+                    p = bp + 1;
+                    v = bv + 1;
+                    backtrack = Some((bp, bv + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn default_batch_size() -> usize {
+    ROLLOUT_DEFAULT_BATCH_SIZE
+}
+
+fn default_timeout() -> u64 {
+    ROLLOUT_DEFAULT_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use eui48::MacAddress;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        Rollout, RolloutClientStatus, RolloutRequest, RolloutStatus, RolloutTarget, cancel_rollout,
+        glob_match, start_rollout,
+    };
+    use crate::{
+        ota::resolve_pending_ota,
+        state::{SharedState, connect_test_client, create_test_state},
+        willow::client::WillowClient,
+    };
+
+    const REBOOT_TIME: Duration = Duration::from_secs(5);
+
+    /// How a fake client handles `ota_start`.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Behavior {
+        /// Ignore the command and never reconnect.
+        Ignore,
+        /// Reconnect still running the old version.
+        Rollback,
+        /// Reconnect running the new version.
+        Update,
+    }
+
+    /// What the fake clients saw during a rollout.
+    #[derive(Default)]
+    struct Observed {
+        /// Status of each client in the rollout right before it reconnected.
+        before_reconnect: Mutex<Vec<RolloutClientStatus>>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        /// Most clients the rollout was updating at once.
+        max_updating: AtomicUsize,
+        /// Hostnames of the clients that got `ota_start`, in order.
+        started: Mutex<Vec<String>>,
+    }
+
+    fn client(hostname: &str, mac_addr: &str, version: &str) -> WillowClient {
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            &format!("Willow/{version}"),
+        );
+        client.set_approved(true);
+        client.set_hostname(hostname.to_string());
+        client.set_mac_addr(MacAddress::parse_str(mac_addr).expect("invalid MAC address"));
+        client.set_platform(String::from("ESP32-S3-BOX"));
+        client
+    }
+
+    async fn connect(state: &SharedState, hostname: &str, mac_addr: &str, version: &str) -> Uuid {
+        let (client_id, mut msg_rx) =
+            connect_test_client(state, client(hostname, mac_addr, version)).await;
+
+        // drain messages to clients that are not driven by a fake client
+        tokio::spawn(async move { while msg_rx.recv().await.is_some() {} });
+
+        client_id
+    }
+
+    /// Connect a fake client running 0.3.0 that handles `ota_start` as `behavior` says.
+    async fn spawn_client(
+        state: &SharedState,
+        observed: &Arc<Observed>,
+        n: usize,
+        behavior: Behavior,
+    ) {
+        let hostname = format!("willow-{n}");
+        let mac_addr = format!("7c:df:a1:e7:a8:{n:02x}");
+
+        let (client_id, mut msg_rx) =
+            connect_test_client(state, client(&hostname, &mac_addr, "0.3.0")).await;
+
+        let state = Arc::clone(state);
+        let observed = Arc::clone(observed);
+        tokio::spawn(async move {
+            while let Some(msg) = msg_rx.recv().await {
+                if !msg.to_text().is_ok_and(|m| m.contains("ota_start")) {
+                    continue;
+                }
+
+                observed
+                    .started
+                    .lock()
+                    .expect("lock poisoned")
+                    .push(hostname.clone());
+                let in_flight = observed.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                observed
+                    .max_in_flight
+                    .fetch_max(in_flight, Ordering::SeqCst);
+                let updating = state.rollout().read().await.as_ref().map_or(0, |r| {
+                    r.clients
+                        .iter()
+                        .filter(|c| c.status == RolloutClientStatus::Updating)
+                        .count()
+                });
+                observed.max_updating.fetch_max(updating, Ordering::SeqCst);
+
+                if behavior == Behavior::Ignore {
+                    continue;
+                }
+
+                // reboot for longer than the rollout polls, which must keep waiting for the client
+                tokio::time::sleep(REBOOT_TIME).await;
+                let status = client_status(&state, &hostname).await;
+                observed
+                    .before_reconnect
+                    .lock()
+                    .expect("lock poisoned")
+                    .push(status);
+                observed.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                state.delete_client(client_id).await;
+                let version = if behavior == Behavior::Update {
+                    "0.3.1"
+                } else {
+                    "0.3.0"
+                };
+                let client_id = connect(&state, &hostname, &mac_addr, version).await;
+                resolve_pending_ota(&state, client_id, &mac_addr)
+                    .await
+                    .expect("failed to resolve pending OTA");
+                return;
+            }
+        });
+    }
+
+    async fn client_status(state: &SharedState, hostname: &str) -> RolloutClientStatus {
+        state
+            .rollout()
+            .read()
+            .await
+            .as_ref()
+            .and_then(|r| r.clients.iter().find(|c| c.hostname == hostname))
+            .map(|c| c.status)
+            .expect("client not in rollout")
+    }
+
+    async fn create_rollout_state() -> SharedState {
+        let state = create_test_state().await;
+        state
+            .db_pool()
+            .save_was_config(&json!({"ota_source": "upstream"}))
+            .await
+            .expect("failed to save WAS config");
+
+        // the database runs in real time, so time can only be paused once it is set up
+        tokio::time::pause();
+
+        state
+    }
+
+    fn request(batch_size: usize) -> RolloutRequest {
+        RolloutRequest {
+            batch_size,
+            target: RolloutTarget::All,
+            timeout: 600,
+            version: String::from("0.3.1"),
+        }
+    }
+
+    /// Wait until the rollout is no longer running, and return it.
+    async fn finished(state: &SharedState) -> Rollout {
+        for _ in 0..1000 {
+            if let Some(rollout) = state.rollout().read().await.as_ref()
+                && rollout.status != RolloutStatus::Running
+            {
+                return rollout.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+
+        panic!("rollout did not finish");
+    }
+
+    fn statuses(rollout: &Rollout, status: RolloutClientStatus) -> Vec<&str> {
+        let mut hostnames: Vec<&str> = rollout
+            .clients
+            .iter()
+            .filter(|c| c.status == status)
+            .map(|c| c.hostname.as_str())
+            .collect();
+        hostnames.sort_unstable();
+        hostnames
+    }
+
+    fn started(observed: &Observed) -> Vec<String> {
+        let mut started = observed.started.lock().expect("lock poisoned").clone();
+        started.sort();
+        started
+    }
+
+    #[tokio::test]
+    async fn test_rollout_batches() {
+        let state = create_rollout_state().await;
+        let observed = Arc::new(Observed::default());
+        for n in 1..=3 {
+            spawn_client(&state, &observed, n, Behavior::Update).await;
+        }
+        connect(&state, "willow-current", "7c:df:a1:e7:a8:ff", "0.3.1").await;
+
+        for request in [
+            RolloutRequest {
+                batch_size: 0,
+                ..request(2)
+            },
+            RolloutRequest {
+                timeout: 0,
+                ..request(2)
+            },
+            RolloutRequest {
+                timeout: u64::MAX,
+                ..request(2)
+            },
+        ] {
+            assert!(start_rollout(&state, request).await.is_err());
+        }
+
+        let rollout = start_rollout(&state, request(2))
+            .await
+            .expect("failed to start rollout");
+        assert_eq!(rollout.clients.len(), 4);
+        assert!(start_rollout(&state, request(2)).await.is_err());
+
+        let rollout = finished(&state).await;
+        assert_eq!(rollout.status, RolloutStatus::Completed);
+        assert_eq!(
+            statuses(&rollout, RolloutClientStatus::Success),
+            ["willow-1", "willow-2", "willow-3"]
+        );
+        assert_eq!(
+            statuses(&rollout, RolloutClientStatus::Skipped),
+            ["willow-current"]
+        );
+
+        // batches of 2, each client updated once and only done after it reconnected with the new version
+        assert_eq!(observed.max_updating.load(Ordering::SeqCst), 2);
+        assert!(observed.max_in_flight.load(Ordering::SeqCst) <= 2);
+        assert_eq!(started(&observed), ["willow-1", "willow-2", "willow-3"]);
+        assert!(
+            observed
+                .before_reconnect
+                .lock()
+                .expect("lock poisoned")
+                .iter()
+                .all(|s| *s == RolloutClientStatus::Updating)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rollout_halt() {
+        let state = create_rollout_state().await;
+        let observed = Arc::new(Observed::default());
+        spawn_client(&state, &observed, 1, Behavior::Update).await;
+        spawn_client(&state, &observed, 2, Behavior::Rollback).await;
+        spawn_client(&state, &observed, 3, Behavior::Rollback).await;
+
+        // whichever clients end up in the first batch, it has a failing client, so the rollout halts after it
+        start_rollout(&state, request(2))
+            .await
+            .expect("failed to start rollout");
+        let rollout = finished(&state).await;
+        assert_eq!(rollout.status, RolloutStatus::Halted);
+        assert_eq!(observed.started.lock().expect("lock poisoned").len(), 2);
+
+        let pending = statuses(&rollout, RolloutClientStatus::Pending);
+        assert_eq!(pending.len(), 1);
+        assert!(!started(&observed).iter().any(|h| h == pending[0]));
+
+        let failed: Vec<_> = rollout
+            .clients
+            .iter()
+            .filter(|c| c.status == RolloutClientStatus::Failed)
+            .collect();
+        assert!(!failed.is_empty());
+        assert!(
+            failed
+                .iter()
+                .all(|c| c.error.as_deref() == Some("OTA failed"))
+        );
+
+        // a halted rollout does not block a new one
+        assert!(start_rollout(&state, request(2)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rollout_cancel() {
+        let state = create_rollout_state().await;
+        let observed = Arc::new(Observed::default());
+        spawn_client(&state, &observed, 1, Behavior::Ignore).await;
+        spawn_client(&state, &observed, 2, Behavior::Ignore).await;
+
+        assert!(cancel_rollout(&state).await.is_err());
+        start_rollout(&state, request(1))
+            .await
+            .expect("failed to start rollout");
+        // the database runs in real time, so time may pass while waiting for the command
+        for _ in 0..10_000 {
+            if !observed.started.lock().expect("lock poisoned").is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        cancel_rollout(&state)
+            .await
+            .expect("failed to cancel rollout");
+        assert!(cancel_rollout(&state).await.is_err());
+
+        // the client being updated stops being waited for, no further batches are started
+        let updating = started(&observed);
+        assert_eq!(updating.len(), 1);
+        for _ in 0..10_000 {
+            if client_status(&state, &updating[0]).await != RolloutClientStatus::Updating {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let rollout = finished(&state).await;
+        assert_eq!(rollout.status, RolloutStatus::Cancelled);
+        let client = rollout
+            .clients
+            .iter()
+            .find(|c| c.hostname == updating[0])
+            .expect("client not in rollout");
+        assert_eq!(client.status, RolloutClientStatus::Failed);
+        assert_eq!(client.error.as_deref(), Some("rollout cancelled"));
+        assert_eq!(statuses(&rollout, RolloutClientStatus::Pending).len(), 1);
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(started(&observed), updating);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "kitchen"));
+        assert!(glob_match("kitchen*", "kitchen-left"));
+        assert!(glob_match("*room", "living room"));
+        assert!(glob_match("office-?", "office-2"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("kitchen*", "bedroom"));
+        assert!(!glob_match("office-?", "office-12"));
+        assert!(!glob_match("", "kitchen"));
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce, aead::Aead};
+use anyhow::{Context, anyhow};
+use serde_json::Value;
+
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const KEY_BYTES: usize = 32;
+const NONCE_BYTES: usize = 12;
+
+/// Placeholder returned by the API instead of secrets, saving it keeps the stored secret.
+pub const REDACTED: &str = "********";
+
+/// Secret settings in the Willow config.
+pub const CONFIG_SECRETS: &[&str] = &[
+    "hass_token",
+    "mqtt_password",
+    "openhab_token",
+    "rest_auth_pass",
+];
+
+/// Secret settings in the Willow NVS config, as namespace and name.
+pub const NVS_SECRETS: &[(&str, &str)] = &[("WIFI", "PSK")];
+
+/// Server key used to encrypt secrets stored in the database with AES-256-GCM.
+pub struct SecretKey {
+    cipher: Aes256Gcm,
+}
+
+impl Debug for SecretKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey")
+    }
+}
+
+impl SecretKey {
+    /// Load the hex encoded key from `path`, or create a new random key there if the file does not exist.
+    ///
+    /// # Errors
+    /// - if the key file cannot be read or created
+    /// - if the key file does not contain a valid key
+    pub fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        let key = match fs::read_to_string(path) {
+            Ok(key) => hex::decode(key.trim())
+                .context(format!("invalid secret key in {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                tracing::info!("creating new secret key {}", path.display());
+                let mut key = vec![0u8; KEY_BYTES];
+                getrandom::fill(&mut key).map_err(|e| anyhow!("failed to generate key: {e}"))?;
+                write_key_file(path, &hex::encode(&key))
+                    .context(format!("failed to create secret key {}", path.display()))?;
+                key
+            }
+            Err(e) => {
+                return Err(e).context(format!("failed to read secret key {}", path.display()));
+            }
+        };
+
+        Self::new(&key)
+    }
+
+    /// # Errors
+    /// - if `key` is not 32 bytes long
+    pub fn new(key: &[u8]) -> anyhow::Result<Self> {
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|_| anyhow!("secret key must be {KEY_BYTES} bytes"))?;
+
+        Ok(Self { cipher })
+    }
+
+    /// # Errors
+    /// - if encryption fails
+    pub fn encrypt(&self, plaintext: &str) -> anyhow::Result<String> {
+        let mut nonce = [0u8; NONCE_BYTES];
+        getrandom::fill(&mut nonce).map_err(|e| anyhow!("failed to generate nonce: {e}"))?;
+
+        let mut data = nonce.to_vec();
+        data.extend(
+            self.cipher
+                .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+                .map_err(|_| anyhow!("failed to encrypt secret"))?,
+        );
+
+        Ok(format!("{ENCRYPTED_PREFIX}{}", hex::encode(data)))
+    }
+
+    /// Decrypt a value created by [`Self::encrypt`], values that are not encrypted are returned as is.
+    ///
+    /// # Errors
+    /// - if the value is not valid or was encrypted with another key
+    pub fn decrypt(&self, value: &str) -> anyhow::Result<String> {
+        let Some(data) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let data = hex::decode(data).context("invalid encrypted secret")?;
+        if data.len() < NONCE_BYTES {
+            return Err(anyhow!("invalid encrypted secret"));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_BYTES);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt secret, was the secret key changed?"))?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+#[must_use]
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Replace secrets that are set in a serialized Willow config with [`REDACTED`].
+pub fn redact_config(config: &mut Value) {
+    for name in CONFIG_SECRETS {
+        redact(config.get_mut(*name));
+    }
+}
+
+/// Replace secrets that are set in a serialized Willow NVS config with [`REDACTED`].
+pub fn redact_nvs(nvs: &mut Value) {
+    for (namespace, name) in NVS_SECRETS {
+        redact(nvs.get_mut(*namespace).and_then(|ns| ns.get_mut(*name)));
+    }
+}
+
+fn redact(value: Option<&mut Value>) {
+    if let Some(value) = value
+        && value.as_str().is_some_and(|v| !v.is_empty())
+    {
+        *value = Value::String(String::from(REDACTED));
+    }
+}
+
+#[cfg(unix)]
+fn write_key_file(path: &Path, key: &str) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    fs::OpenOptions::new()
+        .create_new(true)
+        .mode(0o600)
+        .write(true)
+        .open(path)?
+        .write_all(key.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_key_file(path: &Path, key: &str) -> std::io::Result<()> {
+    fs::write(path, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{REDACTED, SecretKey, is_encrypted, redact_config, redact_nvs};
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = SecretKey::new(&[7u8; 32]).expect("failed to create key");
+
+        let encrypted = key.encrypt("hunter42").expect("failed to encrypt");
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("hunter42"));
+        assert_ne!(
+            encrypted,
+            key.encrypt("hunter42").expect("failed to encrypt")
+        );
+        assert_eq!(
+            key.decrypt(&encrypted).expect("failed to decrypt"),
+            "hunter42"
+        );
+
+        // values saved before encryption was added
+        assert_eq!(
+            key.decrypt("plaintext").expect("failed to decrypt"),
+            "plaintext"
+        );
+
+        let other = SecretKey::new(&[8u8; 32]).expect("failed to create key");
+        assert!(other.decrypt(&encrypted).is_err());
+        assert!(SecretKey::new(&[7u8; 16]).is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut config = json!({"hass_token": "token", "mqtt_password": null, "hass_host": "ha"});
+        redact_config(&mut config);
+        assert_eq!(
+            config,
+            json!({"hass_token": REDACTED, "mqtt_password": null, "hass_host": "ha"})
+        );
+
+        let mut nvs = json!({"WAS": {"URL": "ws://was"}, "WIFI": {"PSK": "psk", "SSID": "ssid"}});
+        redact_nvs(&mut nvs);
+        assert_eq!(nvs["WIFI"]["PSK"], REDACTED);
+        assert_eq!(nvs["WIFI"]["SSID"], "ssid");
+    }
+}
@@ -10,6 +10,11 @@ use uuid::Uuid;
 
 use crate::{
     db::pool::Pool,
+    endpoint::mqtt::MqttClient,
+    notify::NotifyQueue,
+    ota::OtaCache,
+    rollout::Rollout,
+    wake::WakeArbiter,
     willow::{client::WillowClient, worker::WorkerData},
 };
 
@@ -23,7 +28,12 @@ pub struct WasState {
     clients: RwLock<HashMap<Uuid, WillowClient>>,
     connmgr: RwLock<HashMap<Uuid, WebsocketClientMessageSender>>,
     db_pool: Pool,
-    worker_data: WorkerData,
+    mqtt_client: MqttClient,
+    notify_queue: NotifyQueue,
+    ota_cache: OtaCache,
+    rollout: RwLock<Option<Rollout>>,
+    wake_arbiter: WakeArbiter,
+    worker_data: RwLock<Arc<WorkerData>>,
 }
 
 impl WasState {
@@ -33,7 +43,12 @@ impl WasState {
             clients: RwLock::new(HashMap::new()),
             connmgr: RwLock::new(HashMap::new()),
             db_pool,
-            worker_data,
+            mqtt_client: MqttClient::default(),
+            notify_queue: NotifyQueue::default(),
+            ota_cache: OtaCache::from_env(),
+            rollout: RwLock::new(None),
+            wake_arbiter: WakeArbiter::default(),
+            worker_data: RwLock::new(Arc::new(worker_data)),
         }
     }
 
@@ -50,28 +65,48 @@ impl WasState {
         self.clients.write().await.remove(&client_id);
     }
 
-    /// # Errors
-    /// - when no client with the specified hostname is found
-    pub async fn get_client_id_by_hostname(&self, hostname: &str) -> anyhow::Result<Uuid> {
-        let clients = self.clients().read().await.clone();
-        for (id, client) in &clients {
-            if let Some(client_hostname) = &client.hostname() {
-                if client_hostname.eq(hostname) {
-                    return Ok(*id);
-                }
+    pub async fn set_client_label(&self, mac_addr: &str, label: &str) {
+        for client in self.clients.write().await.values_mut() {
+            if client.mac_addr().as_deref() == Some(mac_addr) {
+                client.set_label(Some(label.to_string()).filter(|l| !l.is_empty()));
             }
         }
+    }
 
-        Err(anyhow::format_err!(
-            "client with hostname {hostname} not found"
-        ))
+    /// Get the id of the client with `hostname`, preferring authenticated and approved clients over clients that might
+    /// be impersonating a device.
+    ///
+    /// # Errors
+    /// - when no client with the specified hostname is found
+    pub async fn get_client_id_by_hostname(&self, hostname: &str) -> anyhow::Result<Uuid> {
+        self.clients()
+            .read()
+            .await
+            .iter()
+            .filter(|(_, client)| client.hostname().as_deref() == Some(hostname))
+            .max_by_key(|(_, client)| (client.authenticated(), client.approved()))
+            .map(|(id, _)| *id)
+            .ok_or_else(|| anyhow!("client with hostname {hostname} not found"))
     }
 
     /// # Errors
     /// - when no client with the specified hostname is found
-    /// - when client id is not found in connmgr
-    pub async fn get_msg_tx_by_hostname(&self, hostname: &str) -> anyhow::Result<Sender<Message>> {
+    pub async fn get_mac_addr_by_hostname(&self, hostname: &str) -> anyhow::Result<String> {
         let client_id = self.get_client_id_by_hostname(hostname).await?;
+        self.clients()
+            .read()
+            .await
+            .get(&client_id)
+            .and_then(|c| c.mac_addr().clone())
+            .ok_or_else(|| anyhow!("client with hostname {hostname} has no MAC address"))
+    }
+
+    /// # Errors
+    /// - when client id is not found in connmgr
+    pub async fn get_msg_tx_by_client_id(
+        &self,
+        client_id: Uuid,
+    ) -> anyhow::Result<Sender<Message>> {
         let connmgr = self.connmgr().read().await;
         if let Some(msg_tx) = connmgr.get(&client_id) {
             Ok(msg_tx.clone())
@@ -80,12 +115,116 @@ impl WasState {
         }
     }
 
+    /// # Errors
+    /// - when no client with the specified hostname is found
+    /// - when client id is not found in connmgr
+    pub async fn get_msg_tx_by_hostname(&self, hostname: &str) -> anyhow::Result<Sender<Message>> {
+        let client_id = self.get_client_id_by_hostname(hostname).await?;
+        self.get_msg_tx_by_client_id(client_id).await
+    }
+
     pub fn db_pool(&self) -> &Pool {
         &self.db_pool
     }
 
     #[must_use]
-    pub fn worker_data(&self) -> &WorkerData {
-        &self.worker_data
+    pub fn mqtt_client(&self) -> &MqttClient {
+        &self.mqtt_client
+    }
+
+    #[must_use]
+    pub fn notify_queue(&self) -> &NotifyQueue {
+        &self.notify_queue
+    }
+
+    #[must_use]
+    pub fn ota_cache(&self) -> &OtaCache {
+        &self.ota_cache
+    }
+
+    /// The running rollout, or the last one that finished.
+    pub fn rollout(&self) -> &RwLock<Option<Rollout>> {
+        &self.rollout
+    }
+
+    #[must_use]
+    pub fn wake_arbiter(&self) -> &WakeArbiter {
+        &self.wake_arbiter
+    }
+
+    /// Snapshot of the worker data, refreshed data is swapped in as a whole.
+    pub async fn worker_data(&self) -> Arc<WorkerData> {
+        Arc::clone(&*self.worker_data.read().await)
+    }
+
+    pub async fn set_worker_data(&self, worker_data: WorkerData) {
+        *self.worker_data.write().await = Arc::new(worker_data);
+    }
+}
+
+/// Create a state on a test database with worker data from the test data.
+#[cfg(test)]
+pub(crate) async fn create_test_state() -> SharedState {
+    Arc::new(WasState::new(
+        crate::db::pool::create_test_pool().await,
+        WorkerData::from_test_data(),
+    ))
+}
+
+/// Add `client` as if it connected, and return its id and the receiver for messages sent to it.
+#[cfg(test)]
+pub(crate) async fn connect_test_client(
+    state: &SharedState,
+    client: WillowClient,
+) -> (Uuid, mpsc::Receiver<Message>) {
+    let client_id = Uuid::new_v4();
+    let (msg_tx, msg_rx) = mpsc::channel(32);
+    state.clients().write().await.insert(client_id, client);
+    state.connmgr().write().await.insert(client_id, msg_tx);
+
+    (client_id, msg_rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect_test_client, create_test_state};
+    use crate::willow::client::WillowClient;
+
+    #[tokio::test]
+    async fn test_get_client_id_by_hostname() {
+        let state = create_test_state().await;
+
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_hostname(String::from("willow-kitchen"));
+        let mut approved = client.clone();
+        approved.set_approved(true);
+
+        let (pending_id, _pending_rx) = connect_test_client(&state, client.clone()).await;
+        let (approved_id, _approved_rx) = connect_test_client(&state, approved).await;
+        let (other_pending_id, _other_rx) = connect_test_client(&state, client).await;
+
+        assert_eq!(
+            state
+                .get_client_id_by_hostname("willow-kitchen")
+                .await
+                .expect("failed to get client"),
+            approved_id
+        );
+
+        state.delete_client(approved_id).await;
+        let id = state
+            .get_client_id_by_hostname("willow-kitchen")
+            .await
+            .expect("failed to get client");
+        assert!(id == pending_id || id == other_pending_id);
+        assert!(
+            state
+                .get_client_id_by_hostname("willow-office")
+                .await
+                .is_err()
+        );
     }
 }
@@ -0,0 +1,141 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    state::SharedState,
+    willow::messages::{WillowMsgWakeResult, WillowWakeResult},
+};
+
+#[derive(Debug)]
+struct WakeEvent {
+    client_id: Uuid,
+    volume: f32,
+}
+
+#[derive(Debug, Default)]
+struct WakeSession {
+    events: Vec<WakeEvent>,
+}
+
+impl WakeSession {
+    fn add_event(&mut self, client_id: Uuid, volume: f32) {
+        if let Some(event) = self.events.iter_mut().find(|e| e.client_id == client_id) {
+            event.volume = volume;
+        } else {
+            self.events.push(WakeEvent { client_id, volume });
+        }
+    }
+
+    fn winner(&self) -> Option<Uuid> {
+        self.events
+            .iter()
+            .max_by(|a, b| a.volume.total_cmp(&b.volume))
+            .map(|e| e.client_id)
+    }
+}
+
+/// Collects `wake_start` events from all clients that heard the same wake word and picks the loudest one
+/// once the wake window has passed.
+#[derive(Debug, Default)]
+pub struct WakeArbiter {
+    session: Mutex<Option<WakeSession>>,
+}
+
+/// # Errors
+/// - if multiwake is disabled and we fail to send the `wake_result` to the client
+pub async fn handle_wake_start(
+    state: &SharedState,
+    client_id: Uuid,
+    volume: f32,
+) -> anyhow::Result<()> {
+    let multiwake = match state.db_pool().get_willow_config().await {
+        Ok(config) => config.multiwake(),
+        Err(e) => {
+            tracing::warn!("failed to get Willow config, assuming multiwake is enabled: {e}");
+            true
+        }
+    };
+
+    if !multiwake {
+        tracing::debug!("multiwake disabled, client {client_id} wins by default");
+        return send_wake_result(state, client_id, true).await;
+    }
+
+    let mut session = state.wake_arbiter().session.lock().await;
+    if session.is_none() {
+        let window = state
+            .db_pool()
+            .get_was_config_or_default()
+            .await
+            .wake_window();
+
+        tracing::debug!("starting new wake session with window {window:?}");
+        tokio::spawn(close_wake_session(Arc::clone(state), window));
+    }
+
+    session
+        .get_or_insert_with(WakeSession::default)
+        .add_event(client_id, volume);
+
+    Ok(())
+}
+
+async fn close_wake_session(state: SharedState, window: Duration) {
+    tokio::time::sleep(window).await;
+
+    let Some(session) = state.wake_arbiter().session.lock().await.take() else {
+        return;
+    };
+
+    let winner = session.winner();
+    tracing::debug!("wake session finished, winner: {winner:?}");
+
+    for event in session.events {
+        let won = winner == Some(event.client_id);
+        if let Err(e) = send_wake_result(&state, event.client_id, won).await {
+            tracing::error!("{e:#}");
+        }
+    }
+}
+
+async fn send_wake_result(state: &SharedState, client_id: Uuid, won: bool) -> anyhow::Result<()> {
+    let msg_tx = state.get_msg_tx_by_client_id(client_id).await?;
+    let msg = WillowMsgWakeResult {
+        wake_result: WillowWakeResult { won },
+    };
+    let msg = serde_json::to_string(&msg).context("failed to serialize WillowMsgWakeResult")?;
+
+    msg_tx
+        .send(msg.into())
+        .await
+        .context(format!("failed to send wake_result to client {client_id}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::WakeSession;
+
+    #[test]
+    fn test_wake_session_winner() {
+        let quiet = Uuid::new_v4();
+        let loud = Uuid::new_v4();
+
+        let mut session = WakeSession::default();
+        assert_eq!(session.winner(), None);
+
+        session.add_event(quiet, -20.5);
+        session.add_event(loud, -6.5);
+        assert_eq!(session.winner(), Some(loud));
+
+        session.add_event(quiet, -1.0);
+        assert_eq!(session.winner(), Some(quiet));
+        assert_eq!(session.events.len(), 2);
+    }
+}
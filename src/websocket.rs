@@ -1,6 +1,6 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use anyhow::anyhow;
+use anyhow::{Context, anyhow};
 use axum::{
     Json,
     extract::{
@@ -11,7 +11,10 @@ use axum::{
     response::IntoResponse,
 };
 use futures_util::{SinkExt, StreamExt, stream::SplitSink};
-use reqwest::{StatusCode, header::USER_AGENT};
+use reqwest::{
+    StatusCode,
+    header::{AUTHORIZATION, USER_AGENT},
+};
 use tokio::{
     sync::mpsc,
     time::{Instant, interval},
@@ -19,8 +22,17 @@ use tokio::{
 use uuid::Uuid;
 
 use crate::{
+    auth::hash_token,
+    endpoint::handle_endpoint_cmd,
+    enrollment::{enroll_client, require_approved, require_authenticated},
+    notify::handle_notify_done,
+    ota::{handle_ota_status, resolve_pending_ota},
     state::SharedState,
-    willow::{client::WillowClient, messages::WillowMsg},
+    wake::handle_wake_start,
+    willow::{
+        client::WillowClient,
+        messages::{WillowMsg, WillowMsgCmdDataType, WillowMsgCmdType, WillowMsgConfig},
+    },
 };
 
 pub async fn get_ws(
@@ -43,12 +55,18 @@ pub async fn get_ws(
         return (StatusCode::INTERNAL_SERVER_ERROR, Json(msg)).into_response();
     };
 
+    // device token, checked once the client says hello with its MAC address
+    let token_hash = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| hash_token(token.trim()));
+
+    let mut client = WillowClient::new(addr, user_agent);
+    client.set_token_hash(token_hash);
+
     let client_id = Uuid::new_v4();
-    state
-        .clients()
-        .write()
-        .await
-        .insert(client_id, WillowClient::new(addr, user_agent));
+    state.clients().write().await.insert(client_id, client);
 
     let state_clone = state.clone();
 
@@ -138,36 +156,105 @@ async fn handle_ws_msg_txt(
 
     tracing::debug!("{msg:#?}");
 
+    // a client pending approval could otherwise win wake arbitration, cancel notifications or report OTA status for
+    // the device it claims to be
+    if !matches!(msg, WillowMsg::Goodbye(_) | WillowMsg::Hello(_)) {
+        require_approved(state, client_id)
+            .await
+            .context("ignoring message from client pending enrollment approval")?;
+    }
+
     match msg {
-        WillowMsg::Cmd(v) => {
-            tracing::debug!("{v:?}");
-        }
+        WillowMsg::Cmd(msg) => match (msg.cmd(), msg.data()) {
+            (WillowMsgCmdType::Endpoint, Some(WillowMsgCmdDataType::Endpoint(data))) => {
+                let state = Arc::clone(state);
+                let text = data.text().clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_endpoint_cmd(&state, client_id, &text).await {
+                        tracing::error!("{e:#}");
+                    }
+                });
+            }
+            (WillowMsgCmdType::Endpoint, None) => {
+                tracing::warn!("client {client_id} sent endpoint command without data");
+            }
+            (WillowMsgCmdType::GetConfig, _) => {
+                send_config(state, client_id).await?;
+            }
+        },
         WillowMsg::Goodbye(_) => {
             state.delete_client(client_id).await;
         }
         WillowMsg::Hello(msg) => {
+            let mac_addr = msg.mac_addr()?;
+
             let mut clients = state.clients().write().await;
-            clients
-                .get_mut(&client_id)
-                .ok_or_else(|| anyhow!("client with id {client_id} not found"))?
-                .set_hostname(msg.hostname().clone());
-            clients
+            let client = clients
                 .get_mut(&client_id)
-                .ok_or_else(|| anyhow!("client with id {client_id} not found"))?
-                .set_platform(msg.hw_type().clone());
-            clients
-                .get_mut(&client_id)
-                .ok_or_else(|| anyhow!("client with id {client_id} not found"))?
-                .set_mac_addr(msg.mac_addr()?);
+                .ok_or_else(|| anyhow!("client with id {client_id} not found"))?;
+            client.set_hostname(msg.hostname().clone());
+            client.set_platform(msg.hw_type().clone());
+            client.set_mac_addr(mac_addr);
+            drop(clients);
+
+            let mac_addr = mac_addr.to_hex_string();
+            state.db_pool().save_willow_client(&mac_addr).await?;
+            let approved = enroll_client(state, client_id, &mac_addr).await?;
+            let label = state.db_pool().get_willow_client_label(&mac_addr).await?;
+            if let Some(client) = state.clients().write().await.get_mut(&client_id) {
+                client.set_label(label);
+            }
+            if approved {
+                resolve_pending_ota(state, client_id, &mac_addr).await?;
+            }
         }
-        WillowMsg::WakeEnd(_) | WillowMsg::WakeStart(_) => {
-            tracing::warn!("Willow One Wake not implemented yet");
+        WillowMsg::NotifyDone(id) => {
+            handle_notify_done(state, client_id, id).await?;
+        }
+        WillowMsg::OtaStatus(msg) => {
+            handle_ota_status(state, client_id, &msg).await?;
+        }
+        WillowMsg::WakeEnd(_) => {}
+        WillowMsg::WakeStart(msg) => {
+            handle_wake_start(state, client_id, msg.wake_volume()).await?;
         }
     }
 
     Ok(())
 }
 
+async fn send_config(state: &SharedState, client_id: Uuid) -> anyhow::Result<()> {
+    require_authenticated(state, client_id)
+        .await
+        .context("refusing to send config with secrets")?;
+
+    let config = match state.db_pool().get_willow_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!("failed to get Willow config, sending default config: {e}");
+            let config = state
+                .worker_data()
+                .await
+                .config()
+                .cloned()
+                .ok_or_else(|| anyhow!("no default Willow config available"))?;
+            serde_json::from_value(config).context("failed to deserialize default Willow config")?
+        }
+    };
+
+    let msg = serde_json::to_string(&WillowMsgConfig { config })
+        .context("failed to serialize WillowMsgConfig")?;
+
+    state
+        .get_msg_tx_by_client_id(client_id)
+        .await?
+        .send(msg.into())
+        .await
+        .context(format!("failed to send config to client {client_id}"))?;
+
+    Ok(())
+}
+
 pub async fn send_ping(state: SharedState) {
     loop {
         tokio::time::sleep(Duration::from_secs(10)).await;
@@ -191,7 +278,8 @@ async fn ws_sender(
     client_id: Uuid,
 ) {
     while let Some(msg) = msg_rx.recv().await {
-        tracing::debug!("sending {msg:?} to client {client_id}");
+        // config messages contain secrets, so only log that we send
+        tracing::debug!("sending message to client {client_id}");
         if let Err(e) = ws_tx.send(msg).await {
             tracing::error!("failed to send message to client {client_id}: {e}");
         };
@@ -199,3 +287,189 @@ async fn ws_sender(
 
     tracing::debug!("stopping ws_sender task for client {client_id}");
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Utf8Bytes;
+    use serde_json::json;
+
+    use super::handle_ws_msg_txt;
+    use crate::{
+        auth::hash_token,
+        db::{auth::WillowApiToken, ota::WillowOtaHistory},
+        enrollment::{approve_client, revoke_client},
+        state::{SharedState, connect_test_client, create_test_state},
+        willow::{client::WillowClient, test_util::read_file},
+    };
+
+    const MAC_ADDR: &str = "7c:df:a1:e7:a8:98";
+
+    /// Connect a client presenting `token`, say hello and request config, and return whether config was sent.
+    async fn get_config(state: &SharedState, token: Option<&str>) -> bool {
+        let mut client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        client.set_token_hash(token.map(hash_token));
+        let (client_id, mut msg_rx) = connect_test_client(state, client).await;
+
+        assert!(handle_msg(state, client_id, "test/willow/messages/hello.json").await);
+        let ok = handle_msg(state, client_id, "test/willow/messages/cmd_get_config.json").await;
+        let sent = msg_rx
+            .try_recv()
+            .is_ok_and(|msg| msg.to_text().is_ok_and(|m| m.contains("hass_token")));
+        assert_eq!(ok, sent);
+        state.delete_client(client_id).await;
+
+        sent
+    }
+
+    async fn handle_msg(state: &SharedState, client_id: uuid::Uuid, path: &str) -> bool {
+        handle_ws_msg_txt(state, client_id, &Utf8Bytes::from(read_file(path)))
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_hello_saves_client() {
+        let state = create_test_state().await;
+        let client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.1",
+        );
+        let (client_id, _msg_rx) = connect_test_client(&state, client.clone()).await;
+        assert!(handle_msg(&state, client_id, "test/willow/messages/hello.json").await);
+
+        let clients = state
+            .db_pool()
+            .get_willow_clients()
+            .await
+            .expect("failed to get clients");
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].mac_addr, MAC_ADDR);
+
+        // the label is kept across reconnects
+        state
+            .db_pool()
+            .save_willow_client_label(MAC_ADDR, "Kitchen")
+            .await
+            .expect("failed to save label");
+        state.delete_client(client_id).await;
+        let (client_id, _msg_rx) = connect_test_client(&state, client).await;
+        assert!(handle_msg(&state, client_id, "test/willow/messages/hello.json").await);
+
+        let clients = state.clients().read().await;
+        let client = clients.get(&client_id).expect("client not found");
+        assert_eq!(client.label().as_deref(), Some("Kitchen"));
+        assert_eq!(client.hostname().as_deref(), Some("willow-7cdfa1e7a898"));
+        assert_eq!(
+            state
+                .db_pool()
+                .get_willow_clients()
+                .await
+                .expect("failed to get clients")
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_client_messages() {
+        let state = create_test_state().await;
+        state
+            .db_pool()
+            .save_was_config(&json!({"device_enrollment": "true"}))
+            .await
+            .expect("failed to save WAS config");
+        state
+            .db_pool()
+            .save_ota_history(&WillowOtaHistory {
+                id: String::from("ota"),
+                mac_addr: String::from(MAC_ADDR),
+                hostname: None,
+                version_from: String::from("0.3.0"),
+                version_to: String::from("0.3.1"),
+                status: String::from("done"),
+                started_at: 0,
+                updated_at: 0,
+            })
+            .await
+            .expect("failed to save OTA history");
+
+        let client = WillowClient::new(
+            "127.0.0.1:1234".parse().expect("invalid address"),
+            "Willow/0.3.0",
+        );
+        let (client_id, mut msg_rx) = connect_test_client(&state, client).await;
+
+        assert!(handle_msg(&state, client_id, "test/willow/messages/hello.json").await);
+        for path in [
+            "test/willow/messages/cmd_get_config.json",
+            "test/willow/messages/notify_done.json",
+            "test/willow/messages/ota_status.json",
+            "test/willow/messages/wake_start.json",
+        ] {
+            assert!(!handle_msg(&state, client_id, path).await, "{path}");
+        }
+        assert!(msg_rx.try_recv().is_err());
+
+        // the OTA of the device the client claims to be is left alone
+        let pending = state
+            .db_pool()
+            .get_pending_ota(MAC_ADDR)
+            .await
+            .expect("failed to get pending OTA")
+            .expect("pending OTA was resolved");
+        assert_eq!(pending.status, "done");
+
+        approve_client(&state, MAC_ADDR)
+            .await
+            .expect("failed to approve client");
+        assert!(msg_rx.try_recv().is_ok());
+        assert!(handle_msg(&state, client_id, "test/willow/messages/notify_done.json").await);
+    }
+
+    #[tokio::test]
+    async fn test_get_config_token() {
+        let state = create_test_state().await;
+        state
+            .db_pool()
+            .save_api_token(
+                &WillowApiToken {
+                    id: String::from("token"),
+                    name: String::from("devices"),
+                    username: String::from("admin"),
+                    created_at: 0,
+                    last_used_at: None,
+                },
+                &hash_token("api-token"),
+            )
+            .await
+            .expect("failed to save API token");
+
+        // device enrollment is disabled, so stock clients that do not send a token get config
+        assert!(get_config(&state, None).await);
+        assert!(get_config(&state, Some("wrong-token")).await);
+        assert!(get_config(&state, Some("api-token")).await);
+
+        // with device enrollment, only clients with their approved device token get config
+        state
+            .db_pool()
+            .save_was_config(&json!({"device_enrollment": "true"}))
+            .await
+            .expect("failed to save WAS config");
+        assert!(!get_config(&state, None).await);
+        assert!(!get_config(&state, Some("wrong-token")).await);
+        assert!(!get_config(&state, Some("api-token")).await);
+
+        let device_token = approve_client(&state, MAC_ADDR)
+            .await
+            .expect("failed to approve client");
+        assert!(get_config(&state, Some(&device_token)).await);
+
+        revoke_client(&state, MAC_ADDR)
+            .await
+            .expect("failed to revoke client");
+        assert!(!get_config(&state, Some(&device_token)).await);
+    }
+}
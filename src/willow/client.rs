@@ -2,18 +2,54 @@ use std::net::SocketAddr;
 
 use eui48::MacAddress;
 use serde::Serialize;
+use strum::{AsRefStr, EnumString};
+
+use super::messages::WillowOtaStatus;
 
 #[allow(dead_code)]
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct WillowClient {
+    /// Whether the client may get config and send commands, always true with device enrollment disabled.
+    approved: bool,
+    /// Whether the client presented an API token or its approved device token, required to get config.
+    #[serde(skip)]
+    authenticated: bool,
     hostname: Option<String>,
     ip: String,
+    label: Option<String>,
     mac_addr: Option<String>,
     notification_active: bool,
+    #[serde(skip)]
+    notification_id: Option<i64>,
+    ota: Option<WillowClientOta>,
     platform: Option<String>,
+    /// Hash of the device token presented when connecting.
+    #[serde(skip)]
+    token_hash: Option<String>,
     version: String,
 }
 
+/// Enrollment status of a device, stored in `willow_clients`.
+#[derive(AsRefStr, Clone, Copy, Debug, EnumString, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum WillowClientStatus {
+    Approved,
+    Pending,
+}
+
+/// State of the last OTA of a client, kept across reconnects while the OTA is pending.
+#[derive(Clone, Debug, Serialize)]
+pub struct WillowClientOta {
+    /// Id of the OTA in the history table, `None` for OTAs not started by WAS.
+    #[serde(skip)]
+    pub history_id: Option<String>,
+    pub progress: Option<u8>,
+    pub status: WillowOtaStatus,
+    /// Version the client is being updated to, if known.
+    pub version: Option<String>,
+}
+
 impl WillowClient {
     #[must_use]
     pub fn new(addr: SocketAddr, user_agent: &str) -> Self {
@@ -24,20 +60,91 @@ impl WillowClient {
         }
     }
 
+    #[must_use]
+    pub fn approved(&self) -> bool {
+        self.approved
+    }
+
+    #[must_use]
+    pub fn authenticated(&self) -> bool {
+        self.authenticated
+    }
+
     #[must_use]
     pub fn hostname(&self) -> &Option<String> {
         &self.hostname
     }
 
+    #[must_use]
+    pub fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    #[must_use]
+    pub fn mac_addr(&self) -> &Option<String> {
+        &self.mac_addr
+    }
+
+    #[must_use]
+    pub fn ota(&self) -> &Option<WillowClientOta> {
+        &self.ota
+    }
+
+    #[must_use]
+    pub fn platform(&self) -> &Option<String> {
+        &self.platform
+    }
+
+    pub fn set_approved(&mut self, approved: bool) {
+        self.approved = approved;
+    }
+
+    pub fn set_authenticated(&mut self, authenticated: bool) {
+        self.authenticated = authenticated;
+    }
+
     pub fn set_hostname(&mut self, hostname: String) {
         self.hostname = Some(hostname);
     }
 
+    pub fn set_label(&mut self, label: Option<String>) {
+        self.label = label;
+    }
+
+    /// Id of the notification currently shown on the client.
+    #[must_use]
+    pub fn notification_id(&self) -> Option<i64> {
+        self.notification_id
+    }
+
+    pub fn set_notification_id(&mut self, id: Option<i64>) {
+        self.notification_active = id.is_some();
+        self.notification_id = id;
+    }
+
     pub fn set_mac_addr(&mut self, mac_addr: MacAddress) {
         self.mac_addr = Some(mac_addr.to_hex_string());
     }
 
+    pub fn set_ota(&mut self, ota: Option<WillowClientOta>) {
+        self.ota = ota;
+    }
+
     pub fn set_platform(&mut self, hw_type: String) {
         self.platform = Some(hw_type);
     }
+
+    #[must_use]
+    pub fn token_hash(&self) -> &Option<String> {
+        &self.token_hash
+    }
+
+    pub fn set_token_hash(&mut self, token_hash: Option<String>) {
+        self.token_hash = token_hash;
+    }
+
+    #[must_use]
+    pub fn version(&self) -> &str {
+        &self.version
+    }
 }
@@ -18,9 +18,9 @@ enum WillowAudioResponseType {
     Tts,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
-enum WillowCommandEndpoint {
+pub enum WillowCommandEndpoint {
     #[serde(rename = "Home Assistant")]
     HomeAssistant,
     #[serde(rename = "openHAB")]
@@ -29,9 +29,9 @@ enum WillowCommandEndpoint {
     Rest,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
-enum WillowMqttAuthType {
+pub enum WillowMqttAuthType {
     None,
     UserPw,
 }
@@ -43,8 +43,8 @@ enum WillowNtpConfig {
     Host,
 }
 
-#[derive(Deserialize, Serialize)]
-enum WillowRestAuthType {
+#[derive(Debug, Deserialize, Serialize)]
+pub enum WillowRestAuthType {
     #[serde(rename = "None")]
     NoneType,
     Basic,
@@ -158,12 +158,126 @@ pub struct WillowConfig {
     wis_url: String,
 }
 
+impl WillowConfig {
+    #[must_use]
+    pub fn command_endpoint(&self) -> &WillowCommandEndpoint {
+        &self.command_endpoint
+    }
+
+    #[must_use]
+    pub fn hass_host(&self) -> &Option<String> {
+        &self.hass_host
+    }
+
+    #[must_use]
+    pub fn hass_port(&self) -> Option<u16> {
+        self.hass_port
+    }
+
+    #[must_use]
+    pub fn hass_tls(&self) -> Option<bool> {
+        self.hass_tls
+    }
+
+    #[must_use]
+    pub fn hass_token(&self) -> &Option<String> {
+        &self.hass_token
+    }
+
+    #[must_use]
+    pub fn mqtt_auth_type(&self) -> &Option<WillowMqttAuthType> {
+        &self.mqtt_auth_type
+    }
+
+    #[must_use]
+    pub fn mqtt_host(&self) -> &Option<String> {
+        &self.mqtt_host
+    }
+
+    #[must_use]
+    pub fn mqtt_password(&self) -> &Option<String> {
+        &self.mqtt_password
+    }
+
+    #[must_use]
+    pub fn mqtt_port(&self) -> &Option<String> {
+        &self.mqtt_port
+    }
+
+    #[must_use]
+    pub fn mqtt_tls(&self) -> Option<bool> {
+        self.mqtt_tls
+    }
+
+    #[must_use]
+    pub fn mqtt_topic(&self) -> &Option<String> {
+        &self.mqtt_topic
+    }
+
+    #[must_use]
+    pub fn mqtt_username(&self) -> &Option<String> {
+        &self.mqtt_username
+    }
+
+    #[must_use]
+    pub fn multiwake(&self) -> bool {
+        self.multiwake
+    }
+
+    #[must_use]
+    pub fn openhab_token(&self) -> &Option<String> {
+        &self.openhab_token
+    }
+
+    #[must_use]
+    pub fn openhab_url(&self) -> &Option<String> {
+        &self.openhab_url
+    }
+
+    #[must_use]
+    pub fn rest_auth_header(&self) -> &Option<String> {
+        &self.rest_auth_header
+    }
+
+    #[must_use]
+    pub fn rest_auth_pass(&self) -> &Option<String> {
+        &self.rest_auth_pass
+    }
+
+    #[must_use]
+    pub fn rest_auth_type(&self) -> &Option<WillowRestAuthType> {
+        &self.rest_auth_type
+    }
+
+    #[must_use]
+    pub fn rest_auth_user(&self) -> &Option<String> {
+        &self.rest_auth_user
+    }
+
+    #[must_use]
+    pub fn rest_url(&self) -> &Option<String> {
+        &self.rest_url
+    }
+
+    #[must_use]
+    pub fn show_prereleases(&self) -> bool {
+        self.show_prereleases
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct WillowNvsWas {
     url: String,
 }
 
+impl WillowNvsWas {
+    #[must_use]
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct WillowNvsWifi {
@@ -213,7 +327,7 @@ where
     }
 }
 
-fn deserialize_string_to_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+pub fn deserialize_string_to_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -228,7 +342,7 @@ where
     }
 }
 
-fn deserialize_string_to_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+pub fn deserialize_string_to_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
     T: FromStr,
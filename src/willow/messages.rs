@@ -1,5 +1,7 @@
 use eui48::MacAddress;
 use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use strum::{AsRefStr, EnumString};
 
 use super::config::{WillowConfig, WillowNvsConfig};
 
@@ -8,6 +10,8 @@ use super::config::{WillowConfig, WillowNvsConfig};
 pub enum WillowMsg {
     Goodbye(WillowMsgGoodbyeHello),
     Hello(WillowMsgGoodbyeHello),
+    NotifyDone(i64),
+    OtaStatus(WillowMsgOtaStatus),
     WakeEnd(WillowMsgWakeEnd),
     WakeStart(WillowMsgWakeStart),
     #[serde(untagged)]
@@ -28,6 +32,18 @@ pub struct WillowMsgCmd {
     data: Option<WillowMsgCmdDataType>,
 }
 
+impl WillowMsgCmd {
+    #[must_use]
+    pub fn cmd(&self) -> &WillowMsgCmdType {
+        &self.cmd
+    }
+
+    #[must_use]
+    pub fn data(&self) -> &Option<WillowMsgCmdDataType> {
+        &self.data
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum WillowMsgCmdDataType {
@@ -41,11 +57,93 @@ pub struct WillowMsgCmdEndpointData {
     text: String,
 }
 
+impl WillowMsgCmdEndpointData {
+    #[must_use]
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WillowMsgCmdEndpointResult {
+    pub result: WillowCmdEndpointResult,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WillowCmdEndpointResult {
+    pub ok: bool,
+    pub speech: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "cmd")]
+pub enum WillowAction {
+    Identify,
+    Notify(WillowNotify),
+    OtaStart(WillowOtaStart),
+    Restart,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WillowNotify {
+    pub data: WillowNotifyData,
+}
+
+/// A notification as sent to the device, `id` is the time in milliseconds since the Unix epoch at which the
+/// notification should be shown.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WillowNotifyData {
+    pub audio_url: Option<String>,
+    pub backlight: bool,
+    pub backlight_max: bool,
+    pub cancel: bool,
+    pub id: i64,
+    pub repeat: u32,
+    pub strobe_period_ms: u32,
+    pub text: Option<String>,
+    pub volume: Option<u8>,
+}
+
+impl Default for WillowNotifyData {
+    fn default() -> Self {
+        Self {
+            audio_url: None,
+            backlight: false,
+            backlight_max: false,
+            cancel: false,
+            id: 0,
+            repeat: 1,
+            strobe_period_ms: 0,
+            text: None,
+            volume: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WillowOtaStart {
+    pub ota_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WillowCommand {
+    #[serde(flatten)]
+    pub cmd: WillowAction,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct WillowMsgConfig {
     pub config: WillowConfig,
 }
 
+/// Device token sent to a client when it is approved, to present in the `Authorization` header when connecting.
+#[derive(Serialize)]
+pub struct WillowMsgToken {
+    pub token: String,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct WillowMsgNvs {
     pub config: WillowNvsConfig,
@@ -76,6 +174,29 @@ impl WillowMsgGoodbyeHello {
     }
 }
 
+/// OTA progress reported by the device, `progress` is the percentage of the firmware downloaded so far.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WillowMsgOtaStatus {
+    pub progress: Option<u8>,
+    pub status: WillowOtaStatus,
+}
+
+#[derive(AsRefStr, Clone, Copy, Debug, Deserialize, EnumString, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum WillowOtaStatus {
+    /// OTA was started by WAS.
+    Start,
+    /// Device is downloading the firmware.
+    Progress,
+    /// Device downloaded the firmware and is rebooting.
+    Done,
+    /// Device failed to download or apply the firmware, or came back with the wrong version.
+    Failed,
+    /// Device reconnected running the new version.
+    Success,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct WillowMsgWakeEnd {}
 
@@ -84,22 +205,32 @@ pub struct WillowMsgWakeStart {
     wake_volume: f32,
 }
 
-#[cfg(test)]
-mod tests {
-    use std::{fs::File, io::Read};
+impl WillowMsgWakeStart {
+    #[must_use]
+    pub fn wake_volume(&self) -> f32 {
+        self.wake_volume
+    }
+}
 
-    use super::WillowMsg;
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WillowMsgWakeResult {
+    pub wake_result: WillowWakeResult,
+}
 
-    fn read_file(path: &str) -> String {
-        let mut buf = String::new();
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WillowWakeResult {
+    pub won: bool,
+}
 
-        File::open(path)
-            .unwrap_or_else(|e| panic!("failed to open testdata file '{path}': {e}"))
-            .read_to_string(&mut buf)
-            .unwrap_or_else(|e| panic!("failed to read testdata file '{path}': {e}"));
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
 
-        buf
-    }
+    use super::{
+        WillowAction, WillowCommand, WillowMsg, WillowMsgWakeResult, WillowNotify,
+        WillowNotifyData, WillowWakeResult,
+    };
+    use crate::willow::test_util::read_file;
 
     #[test]
     fn test_deserialize_cmd_endpoint() {
@@ -141,6 +272,26 @@ mod tests {
         println!("{msg:?}");
     }
 
+    #[test]
+    fn test_deserialize_notify_done() {
+        let test_data = read_file("test/willow/messages/notify_done.json");
+
+        let msg: WillowMsg =
+            serde_json::from_str(&test_data).expect("failed to deserialize notify_done message");
+        assert!(matches!(msg, WillowMsg::NotifyDone(1_700_000_000_000)));
+        println!("{msg:?}");
+    }
+
+    #[test]
+    fn test_deserialize_ota_status() {
+        let test_data = read_file("test/willow/messages/ota_status.json");
+
+        let msg: WillowMsg =
+            serde_json::from_str(&test_data).expect("failed to deserialize ota_status message");
+        assert!(matches!(msg, WillowMsg::OtaStatus(_)));
+        println!("{msg:?}");
+    }
+
     #[test]
     fn test_deserialize_wake_end() {
         let test_data = read_file("test/willow/messages/wake_end.json");
@@ -160,4 +311,37 @@ mod tests {
         assert!(matches!(msg, WillowMsg::WakeStart(_)));
         println!("{msg:?}");
     }
+
+    #[test]
+    fn test_serialize_wake_result() {
+        let test_data = read_file("test/willow/messages/wake_result.json");
+        let expected: Value =
+            serde_json::from_str(&test_data).expect("failed to deserialize wake_result test data");
+
+        let msg = WillowMsgWakeResult {
+            wake_result: WillowWakeResult { won: true },
+        };
+        let msg = serde_json::to_value(&msg).expect("failed to serialize wake_result message");
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn test_serialize_notify() {
+        let test_data = read_file("test/willow/messages/notify.json");
+        let expected: Value =
+            serde_json::from_str(&test_data).expect("failed to deserialize notify test data");
+
+        let msg = WillowCommand {
+            cmd: WillowAction::Notify(WillowNotify {
+                data: WillowNotifyData {
+                    id: 1_700_000_000_000,
+                    text: Some(String::from("Dinner is ready")),
+                    volume: Some(60),
+                    ..Default::default()
+                },
+            }),
+        };
+        let msg = serde_json::to_value(&msg).expect("failed to serialize notify message");
+        assert_eq!(msg, expected);
+    }
 }
@@ -1,4 +1,21 @@
 pub mod client;
 pub mod config;
 pub mod messages;
+pub mod release;
 pub mod worker;
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use std::{fs::File, io::Read};
+
+    pub(crate) fn read_file(path: &str) -> String {
+        let mut buf = String::new();
+
+        File::open(path)
+            .unwrap_or_else(|e| panic!("failed to open testdata file '{path}': {e}"))
+            .read_to_string(&mut buf)
+            .unwrap_or_else(|e| panic!("failed to read testdata file '{path}': {e}"));
+
+        buf
+    }
+}
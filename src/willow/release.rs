@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use serde_with::skip_serializing_none;
+
+/// Willow release as returned by the Willow worker.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WillowRelease {
+    #[serde(default)]
+    pub assets: Vec<WillowReleaseAsset>,
+    pub html_url: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    pub published_at: Option<String>,
+    pub tag_name: String,
+    /// Fields of the worker response not known to WAS, passed through to API clients unchanged.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl WillowRelease {
+    /// Check if `version`, as reported by a client, is this release.
+    #[must_use]
+    pub fn is_version(&self, version: &str) -> bool {
+        self.tag_name == version || self.name == version
+    }
+
+    /// Find the OTA build asset for `platform`.
+    #[must_use]
+    pub fn ota_asset(&self, platform: &str) -> Option<&WillowReleaseAsset> {
+        self.assets.iter().find(|a| {
+            a.platform.as_deref() == Some(platform)
+                && a.build_type.as_deref().is_none_or(|t| t == "ota")
+        })
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WillowReleaseAsset {
+    pub browser_download_url: String,
+    pub build_type: Option<String>,
+    /// Checksum of the asset, e.g. `sha256:...`.
+    pub digest: Option<String>,
+    pub name: String,
+    pub platform: Option<String>,
+    pub size: Option<u64>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WillowRelease;
+    use crate::willow::test_util::read_file;
+
+    #[test]
+    fn test_deserialize_releases() {
+        let releases: Vec<WillowRelease> =
+            serde_json::from_str(&read_file("test/willow/worker/releases.json"))
+                .expect("failed to deserialize releases");
+
+        assert_eq!(releases.len(), 2);
+        assert!(!releases[0].prerelease);
+        assert!(releases[1].prerelease);
+        assert!(releases[0].is_version("0.3.1"));
+        assert_eq!(
+            releases[0].ota_asset("ESP32-S3-BOX").and_then(|a| a.size),
+            Some(2_818_048)
+        );
+        assert!(releases[1].ota_asset("ESP32-S3-BOX").is_none());
+    }
+}
@@ -1,84 +1,242 @@
-use reqwest::Url;
+use std::{collections::HashMap, env, time::Duration};
+
+use anyhow::{Context, anyhow};
+use reqwest::{
+    Client, StatusCode, Url,
+    header::{ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+};
 use serde_json::Value;
-use tokio::try_join;
+use strum::IntoStaticStr;
+use tokio::join;
+
+use super::release::{WillowRelease, WillowReleaseAsset};
+use crate::{db::pool::Pool, state::SharedState};
+
+const URL_WILLOW_WORKER: &str = "https://worker.heywillow.org";
+const WORKER_REFRESH_DISABLED_CHECK: Duration = Duration::from_secs(60);
+const WORKER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Payloads fetched from the Willow worker, the name is used as key in the worker cache.
+#[derive(Clone, Copy, Debug, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+enum WorkerPayload {
+    Config,
+    Nvs,
+    Releases,
+    Tz,
+}
+
+impl WorkerPayload {
+    fn path(self) -> &'static str {
+        match self {
+            Self::Config => "api/config?type=config",
+            Self::Nvs => "api/config?type=nvs",
+            Self::Releases => "api/release?format=was",
+            Self::Tz => "api/asset?type=tz",
+        }
+    }
+}
+
+/// `ETag` and `Last-Modified` of a payload, sent back to the worker to only download changed payloads.
+#[derive(Clone, Debug, Default)]
+struct CacheValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
 
-const URL_WILLOW_CONFIG: &str = "https://worker.heywillow.org/api/config";
-const URL_WILLOW_RELEASES: &str = "https://worker.heywillow.org/api/release?format=was";
-const URL_WILLOW_TZ: &str = "https://worker.heywillow.org/api/asset?type=tz";
+impl CacheValidators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+
+        Self {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct WorkerData {
     config: Option<serde_json::Value>,
+    /// Payloads that failed to download in the last refresh.
+    failed: Vec<&'static str>,
     nvs: Option<serde_json::Value>,
-    releases: Option<serde_json::Value>,
+    releases: Option<Vec<WillowRelease>>,
     tz: Option<serde_json::Value>,
+    url: Url,
+    validators: HashMap<&'static str, CacheValidators>,
 }
 
 impl WorkerData {
+    /// Get data from the Willow worker at the URL from `WAS_WORKER_URL`, or the default if unset.
+    ///
     /// # Errors
-    /// - if we fail to get the default Willow config from the worker
-    /// - if we fail to get the default NVS config from the worker
-    /// - if we fail to get releases from the worker
-    /// - if we fail to get tz data from the worker
-    pub async fn create() -> anyhow::Result<Self> {
-        async fn fetch_config() -> anyhow::Result<Value> {
-            let base = Url::parse(URL_WILLOW_CONFIG)?;
-            let url = base.join("?type=config")?;
-            let response = reqwest::get(url).await?;
-            let response = response.error_for_status()?;
-
-            Ok(response.json::<Value>().await?)
-        }
+    /// - if `WAS_WORKER_URL` is not a valid URL
+    pub async fn create(pool: &Pool) -> anyhow::Result<Self> {
+        let url = env::var("WAS_WORKER_URL").unwrap_or(String::from(URL_WILLOW_WORKER));
+        let url = Url::parse(&url).context(format!("invalid WAS_WORKER_URL {url}"))?;
 
-        async fn fetch_nvs() -> anyhow::Result<Value> {
-            let base = Url::parse(URL_WILLOW_CONFIG)?;
-            let url = base.join("?type=nvs")?;
-            let response = reqwest::get(url).await?;
-            let response = response.error_for_status()?;
+        Self::create_with_url(&url, pool).await
+    }
 
-            Ok(response.json::<Value>().await?)
-        }
+    /// Get data from the Willow worker at `url`. Successfully fetched payloads are saved in the database, and
+    /// the saved payloads are used when the worker is unreachable, so WAS can start without internet access.
+    ///
+    /// # Errors
+    /// - if the HTTP client cannot be built
+    pub async fn create_with_url(url: &Url, pool: &Pool) -> anyhow::Result<Self> {
+        let empty = Self {
+            config: None,
+            failed: Vec::new(),
+            nvs: None,
+            releases: None,
+            tz: None,
+            url: url.clone(),
+            validators: HashMap::new(),
+        };
 
-        async fn fetch_releases() -> anyhow::Result<Value> {
-            let url = Url::parse(URL_WILLOW_RELEASES)?;
-            let response = reqwest::get(url).await?;
-            let response = response.error_for_status()?;
+        empty.refresh(pool).await
+    }
 
-            Ok(response.json::<Value>().await?)
-        }
+    /// Get data from the Willow worker again. Payloads the worker reports as not modified, or that fail to
+    /// download, keep their current value.
+    ///
+    /// # Errors
+    /// - if the HTTP client cannot be built
+    pub async fn refresh(&self, pool: &Pool) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(WORKER_TIMEOUT).build()?;
 
-        async fn fetch_tz() -> anyhow::Result<Value> {
-            let url = Url::parse(URL_WILLOW_TZ)?;
-            let response = reqwest::get(url).await?;
-            let response = response.error_for_status()?;
+        let (config, nvs, releases, tz) = join!(
+            self.refresh_payload(&client, pool, WorkerPayload::Config, self.config.clone()),
+            self.refresh_payload(&client, pool, WorkerPayload::Nvs, self.nvs.clone()),
+            self.refresh_payload(
+                &client,
+                pool,
+                WorkerPayload::Releases,
+                self.releases
+                    .as_ref()
+                    .and_then(|r| serde_json::to_value(r).ok())
+            ),
+            self.refresh_payload(&client, pool, WorkerPayload::Tz, self.tz.clone()),
+        );
 
-            Ok(response.json::<Value>().await?)
-        }
+        let validators = [
+            (WorkerPayload::Config, &config),
+            (WorkerPayload::Nvs, &nvs),
+            (WorkerPayload::Releases, &releases),
+            (WorkerPayload::Tz, &tz),
+        ]
+        .into_iter()
+        .map(|(payload, (_, validators, _))| (payload.into(), validators.clone()))
+        .collect();
+
+        let mut failed: Vec<&'static str> = [
+            (WorkerPayload::Config, config.2),
+            (WorkerPayload::Nvs, nvs.2),
+            (WorkerPayload::Releases, releases.2),
+            (WorkerPayload::Tz, tz.2),
+        ]
+        .into_iter()
+        .filter(|(_, ok)| !ok)
+        .map(|(payload, _)| payload.into())
+        .collect();
 
-        let (config, nvs, releases, tz) =
-            try_join!(fetch_config(), fetch_nvs(), fetch_releases(), fetch_tz(),)?;
+        let releases = match releases.0.map(serde_json::from_value).transpose() {
+            Ok(releases) => releases,
+            Err(e) => {
+                tracing::error!("invalid releases from Willow worker, keeping current: {e}");
+                if !failed.contains(&WorkerPayload::Releases.into()) {
+                    failed.push(WorkerPayload::Releases.into());
+                }
+                self.releases.clone()
+            }
+        };
 
         Ok(Self {
-            config: Some(config),
-            nvs: Some(nvs),
-            releases: Some(releases),
-            tz: Some(tz),
+            config: config.0,
+            failed,
+            nvs: nvs.0,
+            releases,
+            tz: tz.0,
+            url: self.url.clone(),
+            validators,
         })
     }
 
+    async fn refresh_payload(
+        &self,
+        client: &Client,
+        pool: &Pool,
+        payload: WorkerPayload,
+        current: Option<Value>,
+    ) -> (Option<Value>, CacheValidators, bool) {
+        let name: &'static str = payload.into();
+        let validators = self.validators.get(name).cloned().unwrap_or_default();
+
+        match fetch(
+            client,
+            &self.url,
+            payload,
+            current.as_ref().map(|_| &validators),
+        )
+        .await
+        {
+            Ok(Some((value, validators))) => {
+                if let Err(e) = pool.save_worker_cache(name, &value).await {
+                    tracing::warn!("failed to save {name} from Willow worker: {e}");
+                }
+                (Some(value), validators, true)
+            }
+            Ok(None) => {
+                tracing::debug!("{name} from Willow worker not modified");
+                (current, validators, true)
+            }
+            Err(e) if current.is_some() => {
+                tracing::warn!("failed to get {name} from Willow worker, keeping current: {e:#}");
+                (current, validators, false)
+            }
+            Err(e) => {
+                tracing::warn!("failed to get {name} from Willow worker, using saved copy: {e:#}");
+                let value = match pool.get_worker_cache(name).await {
+                    Ok(Some(value)) => Some(value),
+                    Ok(None) => {
+                        tracing::error!("no saved copy of {name} from Willow worker available");
+                        None
+                    }
+                    Err(e) => {
+                        tracing::error!("failed to get saved {name} from Willow worker: {e}");
+                        None
+                    }
+                };
+                (value, CacheValidators::default(), false)
+            }
+        }
+    }
+
     #[must_use]
     pub fn config(&self) -> Option<&Value> {
         self.config.as_ref()
     }
 
+    /// Names of the payloads that failed to download in the last refresh and kept their previous value.
+    #[must_use]
+    pub fn failed(&self) -> &[&'static str] {
+        &self.failed
+    }
+
     #[must_use]
     pub fn nvs(&self) -> Option<&Value> {
         self.nvs.as_ref()
     }
 
     #[must_use]
-    pub fn releases(&self) -> Option<&Value> {
+    pub fn releases(&self) -> Option<&Vec<WillowRelease>> {
         self.releases.as_ref()
     }
 
@@ -86,4 +244,229 @@ impl WorkerData {
     pub fn tz(&self) -> Option<&Value> {
         self.tz.as_ref()
     }
+
+    /// Find the OTA build asset of release `version` for `platform`.
+    #[must_use]
+    pub fn ota_asset(&self, version: &str, platform: &str) -> Option<&WillowReleaseAsset> {
+        self.releases
+            .as_ref()?
+            .iter()
+            .find(|r| r.is_version(version))?
+            .ota_asset(platform)
+    }
+}
+
+/// Get data from the Willow worker and replace the data in `state` with it. Payloads that fail to download keep
+/// their current value.
+///
+/// # Errors
+/// - if the HTTP client cannot be built
+/// - if any payload failed to download, after the others were replaced
+pub async fn refresh_worker_data(state: &SharedState) -> anyhow::Result<()> {
+    let worker_data = state.worker_data().await;
+    let worker_data = worker_data.refresh(state.db_pool()).await?;
+    let failed = worker_data.failed().join(", ");
+    state.set_worker_data(worker_data).await;
+
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "failed to get {failed} from Willow worker, keeping current data"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Refresh the worker data in the interval configured in the WAS config.
+pub async fn worker_refresher(state: SharedState) {
+    loop {
+        let interval = state
+            .db_pool()
+            .get_was_config_or_default()
+            .await
+            .worker_refresh_interval();
+
+        if interval.is_zero() {
+            tokio::time::sleep(WORKER_REFRESH_DISABLED_CHECK).await;
+            continue;
+        }
+
+        tokio::time::sleep(interval).await;
+
+        tracing::debug!("refreshing worker data");
+        if let Err(e) = refresh_worker_data(&state).await {
+            tracing::error!("failed to refresh worker data: {e:#}");
+        }
+    }
+}
+
+/// Get a payload from the worker, returns `None` if `validators` are passed and the payload was not modified.
+async fn fetch(
+    client: &Client,
+    base: &Url,
+    payload: WorkerPayload,
+    validators: Option<&CacheValidators>,
+) -> anyhow::Result<Option<(Value, CacheValidators)>> {
+    let url = base.join(payload.path())?;
+    let mut request = client.get(url);
+
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let validators = CacheValidators::from_headers(response.headers());
+
+    Ok(Some((response.json::<Value>().await?, validators)))
+}
+
+/// Worker data from the test data, without contacting the Willow worker.
+#[cfg(test)]
+impl WorkerData {
+    pub(crate) fn from_test_data() -> Self {
+        use super::test_util::read_file;
+
+        Self {
+            config: serde_json::from_str(&read_file("test/willow/config/config.json")).ok(),
+            failed: Vec::new(),
+            nvs: None,
+            releases: serde_json::from_str(&read_file("test/willow/worker/releases.json")).ok(),
+            tz: None,
+            url: Url::parse(URL_WILLOW_WORKER).expect("invalid worker URL"),
+            validators: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reqwest::Url;
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path, query_param},
+    };
+
+    use super::{URL_WILLOW_WORKER, WorkerData};
+    use crate::{
+        db::pool::create_test_pool,
+        willow::{release::WillowRelease, test_util::read_file},
+    };
+
+    #[test]
+    fn test_ota_asset() {
+        let releases: Vec<WillowRelease> =
+            serde_json::from_str(&read_file("test/willow/worker/releases.json"))
+                .expect("failed to deserialize releases");
+        let worker_data = WorkerData {
+            config: None,
+            failed: Vec::new(),
+            nvs: None,
+            releases: Some(releases),
+            tz: None,
+            url: Url::parse(URL_WILLOW_WORKER).expect("failed to parse worker URL"),
+            validators: HashMap::new(),
+        };
+
+        let asset = worker_data
+            .ota_asset("0.3.1", "ESP32-S3-BOX-3")
+            .expect("OTA asset not found");
+        assert_eq!(
+            asset.browser_download_url,
+            "https://github.com/toverainc/willow/releases/download/0.3.1/willow-ota-ESP32-S3-BOX-3.bin"
+        );
+        assert!(
+            worker_data
+                .ota_asset("0.4.0-rc.1", "ESP32-S3-BOX")
+                .is_none()
+        );
+        assert!(worker_data.ota_asset("0.2.0", "ESP32-S3-BOX").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_with_saved_copy() {
+        let pool = create_test_pool().await;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/config"))
+            .and(query_param("type", "config"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"wake_word": "alexa"})))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/release"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&server.uri()).expect("failed to parse mock server URI");
+
+        let worker_data = WorkerData::create_with_url(&url, &pool)
+            .await
+            .expect("failed to create WorkerData");
+        assert_eq!(worker_data.config(), Some(&json!({"wake_word": "alexa"})));
+        assert_eq!(worker_data.releases().map(Vec::len), Some(0));
+        assert!(worker_data.nvs().is_none());
+        assert!(worker_data.tz().is_none());
+        assert_eq!(worker_data.failed(), ["nvs", "tz"]);
+
+        server.reset().await;
+
+        let worker_data = WorkerData::create_with_url(&url, &pool)
+            .await
+            .expect("failed to create WorkerData");
+        assert_eq!(worker_data.config(), Some(&json!({"wake_word": "alexa"})));
+        assert_eq!(worker_data.releases().map(Vec::len), Some(0));
+        assert!(worker_data.nvs().is_none());
+        assert_eq!(worker_data.failed(), ["config", "nvs", "releases", "tz"]);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_not_modified() {
+        let pool = create_test_pool().await;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/release"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/release"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_json(json!([{"name": "0.3.1", "tag_name": "0.3.1"}])),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let url = Url::parse(&server.uri()).expect("failed to parse mock server URI");
+
+        let worker_data = WorkerData::create_with_url(&url, &pool)
+            .await
+            .expect("failed to create WorkerData");
+        let worker_data = worker_data
+            .refresh(&pool)
+            .await
+            .expect("failed to refresh WorkerData");
+        assert_eq!(
+            worker_data.releases().map(|r| r[0].tag_name.as_str()),
+            Some("0.3.1")
+        );
+        assert_eq!(worker_data.failed(), ["config", "nvs", "tz"]);
+    }
 }
@@ -0,0 +1,67 @@
+CREATE TABLE willow_clients (
+	id INTEGER NOT NULL,
+	mac_addr VARCHAR NOT NULL,
+	label VARCHAR NOT NULL,
+	PRIMARY KEY (id),
+	UNIQUE (mac_addr)
+);
+
+CREATE TABLE willow_config (
+	id INTEGER NOT NULL,
+	config_type VARCHAR(8) NOT NULL,
+	config_name VARCHAR NOT NULL,
+	config_namespace VARCHAR(4),
+	config_value VARCHAR,
+	PRIMARY KEY (id),
+	UNIQUE (config_type, config_name)
+);
+
+CREATE TABLE notifications (
+	id INTEGER NOT NULL,
+	hostname VARCHAR NOT NULL,
+	data VARCHAR NOT NULL,
+	PRIMARY KEY (id)
+);
+
+INSERT INTO willow_clients (mac_addr, label) VALUES
+	('24:0A:C4:00:00:01', 'kitchen'),
+	('24:0a:c4:00:00:02', ''),
+	('invalid', 'office');
+
+INSERT INTO willow_config (config_type, config_name, config_namespace, config_value) VALUES
+	('config', 'aec', NULL, 'true'),
+	('config', 'audio_codec', NULL, 'PCM'),
+	('config', 'audio_response_type', NULL, 'TTS'),
+	('config', 'bss', NULL, 'false'),
+	('config', 'command_endpoint', NULL, 'Home Assistant'),
+	('config', 'display_timeout', NULL, '10'),
+	('config', 'hass_host', NULL, 'ha.example.com'),
+	('config', 'hass_port', NULL, '8123'),
+	('config', 'hass_tls', NULL, 'false'),
+	('config', 'hass_token', NULL, 'token'),
+	('config', 'lcd_brightness', NULL, '500'),
+	('config', 'mic_gain', NULL, '14'),
+	('config', 'multiwake', NULL, 'false'),
+	('config', 'ntp_config', NULL, 'Host'),
+	('config', 'ntp_host', NULL, 'pool.ntp.org'),
+	('config', 'record_buffer', NULL, '12'),
+	('config', 'show_prereleases', NULL, 'false'),
+	('config', 'speaker_volume', NULL, '60'),
+	('config', 'stream_timeout', NULL, '5'),
+	('config', 'timezone', NULL, 'UTC+5'),
+	('config', 'timezone_name', NULL, 'America/Chicago'),
+	('config', 'vad_mode', NULL, '2'),
+	('config', 'vad_timeout', NULL, '300'),
+	('config', 'wake_confirmation', NULL, 'false'),
+	('config', 'wake_mode', NULL, '2CH_90'),
+	('config', 'wake_word', NULL, 'hiesp'),
+	('config', 'was_mode', NULL, 'true'),
+	('config', 'wis_tts_url', NULL, 'https://infer.tovera.io/api/tts'),
+	('config', 'wis_url', NULL, 'https://infer.tovera.io/api/willow'),
+	('config', 'old_setting', NULL, '1'),
+	('nvs', 'URL', 'WAS', 'ws://was.example.com:8502/ws'),
+	('nvs', 'PSK', 'WIFI', 'secret'),
+	('nvs', 'SSID', 'WIFI', 'willow');
+
+INSERT INTO notifications (hostname, data) VALUES
+	('willow-kitchen', '{"audio_url": null, "backlight": true, "backlight_max": false, "cancel": false, "id": 1760000000000, "repeat": 1, "strobe_period_ms": 0, "text": "Dinner is ready", "volume": null}');